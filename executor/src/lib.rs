@@ -1,26 +1,180 @@
-#[macro_use]
-extern crate lazy_static;
-
 use std::future::Future;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
 
 use futures::executor::{ThreadPool, ThreadPoolBuilder};
+use futures::future::BoxFuture;
+use futures::FutureExt;
+pub use timer::{delay, Delay};
 
-lazy_static! {
-    static ref EXECUTOR: SandFlowExecutor = init();
-}
+mod timer;
+
+const DEFAULT_POOL_SIZE: usize = 16;
+
+static EXECUTOR: OnceLock<SandFlowExecutor> = OnceLock::new();
 
+/// Spawn a task on the global executor, which is created with default config if not installed;
 pub fn spawn<F>(task: F)
 where
     F: Future<Output = ()> + Send + 'static,
 {
-    EXECUTOR.spawn(task)
+    EXECUTOR.get_or_init(init).spawn(task)
+}
+
+/// Install the global executor created with `config`, it can only be installed once before any task is spawned on it,
+/// return false if the global executor already exists;
+pub fn install(config: ExecutorConfig) -> bool {
+    let mut is_installed = false;
+    EXECUTOR.get_or_init(|| {
+        is_installed = true;
+        SandFlowExecutor::new(config)
+    });
+    is_installed
+}
+
+/// Runtime to run tasks of jobs on;
+pub trait Spawner: Send + Sync + 'static {
+    fn spawn_boxed(&self, task: BoxFuture<'static, ()>);
+
+    /// Create a future which is ready after `duration`; by default it's fired by the dedicated timer thread shared by
+    /// all jobs (see `delay`), runtimes which have their own timers should override it;
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        delay(duration).boxed()
+    }
+}
+
+/// Spawn tasks on the global executor;
+#[derive(Clone, Copy, Default)]
+pub struct GlobalSpawner;
+
+impl Spawner for GlobalSpawner {
+    fn spawn_boxed(&self, task: BoxFuture<'static, ()>) {
+        spawn(task)
+    }
+}
+
+/// Spawn tasks on a tokio runtime, timers of the runtime are used to sleep, so it must be built with timers enabled,
+/// e.g. by `enable_time` or `enable_all`, otherwise timed operators such as `then_retry` and windows panic;
+#[cfg(feature = "tokio")]
+#[derive(Clone)]
+pub struct TokioSpawner {
+    handle: tokio::runtime::Handle,
+}
+
+#[cfg(feature = "tokio")]
+impl TokioSpawner {
+    pub fn new(handle: tokio::runtime::Handle) -> Self {
+        Self { handle }
+    }
+
+    /// Spawn tasks on the runtime of current context, panic if it's not called in a tokio runtime;
+    pub fn current() -> Self {
+        Self { handle: tokio::runtime::Handle::current() }
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl Spawner for TokioSpawner {
+    fn spawn_boxed(&self, task: BoxFuture<'static, ()>) {
+        self.handle.spawn(task);
+    }
+
+    /// Panic if timers of the runtime are not enabled;
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        let _guard = self.handle.enter();
+        tokio::time::sleep(duration).boxed()
+    }
+}
+
+type ThreadHook = Arc<dyn Fn(usize) + Send + Sync + 'static>;
+
+#[derive(Clone, Default)]
+pub struct ExecutorConfig {
+    /// The size of threads in pool, `SANDFLOW_EXECUTOR_THREADS` is used if it's not set;
+    pool_size: Option<usize>,
+    /// The prefix of thread names, thread index is appended to it;
+    name_prefix: Option<String>,
+    /// The stack size of threads in pool;
+    stack_size: Option<usize>,
+    /// Called in each thread with its index after it is started;
+    after_start: Option<ThreadHook>,
+    /// Called in each thread with its index before it is stopped;
+    before_stop: Option<ThreadHook>,
 }
 
+impl ExecutorConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn pool_size(mut self, size: usize) -> Self {
+        assert!(size > 0, "pool size should be larger than 0");
+        self.pool_size = Some(size);
+        self
+    }
+
+    pub fn name_prefix<S: Into<String>>(mut self, name_prefix: S) -> Self {
+        self.name_prefix = Some(name_prefix.into());
+        self
+    }
+
+    pub fn stack_size(mut self, stack_size: usize) -> Self {
+        self.stack_size = Some(stack_size);
+        self
+    }
+
+    pub fn after_start<F>(mut self, f: F) -> Self
+    where
+        F: Fn(usize) + Send + Sync + 'static,
+    {
+        self.after_start = Some(Arc::new(f));
+        self
+    }
+
+    pub fn before_stop<F>(mut self, f: F) -> Self
+    where
+        F: Fn(usize) + Send + Sync + 'static,
+    {
+        self.before_stop = Some(Arc::new(f));
+        self
+    }
+
+    pub fn get_pool_size(&self) -> usize {
+        self.pool_size.unwrap_or_else(|| {
+            std::env::var("SANDFLOW_EXECUTOR_THREADS")
+                .ok()
+                .and_then(|val| val.parse::<usize>().ok())
+                .filter(|size| *size > 0)
+                .unwrap_or(DEFAULT_POOL_SIZE)
+        })
+    }
+}
+
+#[derive(Clone)]
 pub struct SandFlowExecutor {
     pool: ThreadPool,
 }
 
 impl SandFlowExecutor {
+    pub fn new(config: ExecutorConfig) -> Self {
+        let mut pool_builder = ThreadPoolBuilder::new();
+        pool_builder.pool_size(config.get_pool_size());
+        if let Some(name_prefix) = config.name_prefix {
+            pool_builder.name_prefix(name_prefix);
+        }
+        if let Some(stack_size) = config.stack_size {
+            pool_builder.stack_size(stack_size);
+        }
+        if let Some(after_start) = config.after_start {
+            pool_builder.after_start(move |index| after_start(index));
+        }
+        if let Some(before_stop) = config.before_stop {
+            pool_builder.before_stop(move |index| before_stop(index));
+        }
+        let pool = pool_builder.create().expect("create executor failure;");
+        SandFlowExecutor { pool }
+    }
+
     pub fn spawn<F>(&self, task: F)
     where
         F: Future<Output = ()> + Send + 'static,
@@ -29,9 +183,12 @@ impl SandFlowExecutor {
     }
 }
 
+impl Spawner for SandFlowExecutor {
+    fn spawn_boxed(&self, task: BoxFuture<'static, ()>) {
+        self.pool.spawn_ok(task)
+    }
+}
+
 pub fn init() -> SandFlowExecutor {
-    let mut pool_builder = ThreadPoolBuilder::new();
-    pool_builder.pool_size(16);
-    let pool = pool_builder.create().expect("create executor failure;");
-    SandFlowExecutor { pool }
+    SandFlowExecutor::new(ExecutorConfig::default())
 }
@@ -0,0 +1,157 @@
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Condvar, Mutex, OnceLock};
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
+
+static TIMER: OnceLock<Arc<Timer>> = OnceLock::new();
+
+/// Create a future which is ready after `duration`;
+///
+/// All delays are fired by one dedicated thread named `sandflow-timer`, which is started on the first call and parks
+/// on a condvar until the earliest deadline, it is not a thread of any executor; a delay dropped before it's fired
+/// is removed from the timer;
+pub fn delay(duration: Duration) -> Delay {
+    let state = Arc::new(Mutex::new(DelayState { is_fired: false, waker: None }));
+    let timer = TIMER.get_or_init(Timer::start);
+    let key = timer.add(Instant::now() + duration, state.clone());
+    Delay { key, state }
+}
+
+pub struct Delay {
+    key: (Instant, u64),
+    state: Arc<Mutex<DelayState>>,
+}
+
+impl Future for Delay {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.state.lock().expect("lock delay fail;");
+        if state.is_fired {
+            Poll::Ready(())
+        } else {
+            match state.waker.as_ref() {
+                Some(waker) if waker.will_wake(cx.waker()) => (),
+                _ => state.waker = Some(cx.waker().clone()),
+            }
+            Poll::Pending
+        }
+    }
+}
+
+impl Drop for Delay {
+    fn drop(&mut self) {
+        let is_fired = self.state.lock().expect("lock delay fail;").is_fired;
+        if !is_fired {
+            if let Some(timer) = TIMER.get() {
+                timer.remove(&self.key);
+            }
+        }
+    }
+}
+
+struct DelayState {
+    is_fired: bool,
+    waker: Option<Waker>,
+}
+
+struct Entries {
+    // ordered by deadline, and by the order they are added among the same deadline;
+    delays: BTreeMap<(Instant, u64), Arc<Mutex<DelayState>>>,
+    next_seq: u64,
+}
+
+struct Timer {
+    entries: Mutex<Entries>,
+    cond: Condvar,
+}
+
+impl Timer {
+    fn start() -> Arc<Timer> {
+        let entries = Entries { delays: BTreeMap::new(), next_seq: 0 };
+        let timer = Arc::new(Timer { entries: Mutex::new(entries), cond: Condvar::new() });
+        let runner = timer.clone();
+        std::thread::Builder::new()
+            .name("sandflow-timer".to_owned())
+            .spawn(move || runner.run())
+            .expect("start timer thread failure;");
+        timer
+    }
+
+    fn add(&self, deadline: Instant, state: Arc<Mutex<DelayState>>) -> (Instant, u64) {
+        let mut entries = self.entries.lock().expect("lock timer fail;");
+        let key = (deadline, entries.next_seq);
+        entries.next_seq += 1;
+        entries.delays.insert(key, state);
+        self.cond.notify_one();
+        key
+    }
+
+    fn remove(&self, key: &(Instant, u64)) {
+        self.entries
+            .lock()
+            .expect("lock timer fail;")
+            .delays
+            .remove(key);
+    }
+
+    fn run(&self) {
+        let mut entries = self.entries.lock().expect("lock timer fail;");
+        loop {
+            let now = Instant::now();
+            let mut fired = vec![];
+            while let Some(entry) = entries.delays.first_entry() {
+                if entry.key().0 > now {
+                    break;
+                }
+                fired.push(entry.remove());
+            }
+
+            if !fired.is_empty() {
+                drop(entries);
+                for state in fired {
+                    let mut state = state.lock().expect("lock delay fail;");
+                    state.is_fired = true;
+                    if let Some(waker) = state.waker.take() {
+                        waker.wake();
+                    }
+                }
+                entries = self.entries.lock().expect("lock timer fail;");
+                continue;
+            }
+
+            entries = match entries.delays.keys().next().map(|(deadline, _)| *deadline) {
+                Some(deadline) => {
+                    self.cond
+                        .wait_timeout(entries, deadline - now)
+                        .expect("wait timer fail;")
+                        .0
+                }
+                None => self.cond.wait(entries).expect("wait timer fail;"),
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn delay_is_fired() {
+        let start = Instant::now();
+        futures::executor::block_on(delay(Duration::from_millis(20)));
+        assert!(start.elapsed() >= Duration::from_millis(20));
+    }
+
+    #[test]
+    fn dropped_delay_is_removed() {
+        let delays = (0..100).map(|_| delay(Duration::from_secs(3600))).collect::<Vec<_>>();
+        let keys = delays.iter().map(|d| d.key).collect::<Vec<_>>();
+        drop(delays);
+        let entries = TIMER.get().unwrap().entries.lock().unwrap();
+        assert!(keys.iter().all(|key| !entries.delays.contains_key(key)));
+    }
+}
@@ -0,0 +1,16 @@
+use futures::StreamExt;
+
+fn main() {
+    let lines = vec!["a quick brown fox", "jumps over", "the lazy dog", "a lazy fox"];
+    let source = futures::stream::iter(lines).map(Ok);
+    let result = sandflow::spawn(source, || {
+        move |src| {
+            src.flat_map(|line| futures::stream::iter(line.split_whitespace().map(|w| Ok((w.to_owned(), 1)))))
+                .reduce_by_key(|(word, _)| word.clone(), |(word, a), (_, b)| (word, a + b))
+                .map(|item| item.map(|(_, word_count)| word_count))
+        }
+    })
+    .collect::<Vec<_>>();
+    let r = futures::executor::block_on(result);
+    println!("{:?}", r);
+}
@@ -0,0 +1,113 @@
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::FutureExt;
+use sandflow_executor::{GlobalSpawner, Spawner};
+
+const DEFAULT_CHANNEL_CAPACITY: usize = 1024;
+
+/// Configurations of a job;
+#[derive(Clone)]
+pub struct JobConfig {
+    pub(crate) job_id: u64,
+    pub(crate) parallel: usize,
+    pub(crate) spawner: Arc<dyn Spawner>,
+    pub(crate) max_errors: usize,
+    pub(crate) memory_budget: Option<usize>,
+    pub(crate) spill_dir: Option<PathBuf>,
+    pub(crate) channel_capacity: usize,
+    pub(crate) result_capacity: usize,
+    pub(crate) idle_timeout: Option<Duration>,
+}
+
+impl JobConfig {
+    pub fn new(job_id: u64, parallel: usize) -> Self {
+        assert!(parallel > 0, "parallel should be larger than 0");
+        Self {
+            job_id,
+            parallel,
+            spawner: Arc::new(GlobalSpawner),
+            max_errors: 1,
+            memory_budget: None,
+            spill_dir: None,
+            channel_capacity: DEFAULT_CHANNEL_CAPACITY,
+            result_capacity: DEFAULT_CHANNEL_CAPACITY,
+            idle_timeout: None,
+        }
+    }
+
+    /// Run tasks of the job by `spawner` instead of the global executor;
+    pub fn spawner<S: Spawner>(mut self, spawner: S) -> Self {
+        self.spawner = Arc::new(spawner);
+        self
+    }
+
+    /// Keep up to `max` errors of different stages instead of only the first one, they are returned as one error
+    /// by the `ResultStream` after all tasks are aborted; the job is still aborted once the first error occurred;
+    pub fn collect_errors(mut self, max: usize) -> Self {
+        assert!(max > 0, "at least one error should be kept");
+        self.max_errors = max;
+        self
+    }
+
+    /// Limit memory used by spillable operators of the job to about `bytes` in total, their states are written to
+    /// disk once it's exceeded; they keep all states in memory if it's not set;
+    pub fn memory_budget(mut self, bytes: usize) -> Self {
+        assert!(bytes > 0, "memory budget should be larger than 0");
+        self.memory_budget = Some(bytes);
+        self
+    }
+
+    /// Directory to write spilled states to, the temp directory of the system is used if it's not set;
+    pub fn spill_dir<P: Into<PathBuf>>(mut self, dir: P) -> Self {
+        self.spill_dir = Some(dir.into());
+        self
+    }
+
+    /// Default capacity of channels between stages, including channels from the source to workers, it can be
+    /// overridden by each exchange, e.g. `exchange_with_capacity`;
+    pub fn channel_capacity(mut self, capacity: usize) -> Self {
+        self.channel_capacity = capacity;
+        self
+    }
+
+    /// Capacity of the channel which results of the job are sent to the `ResultStream` by;
+    pub fn result_capacity(mut self, capacity: usize) -> Self {
+        self.result_capacity = capacity;
+        self
+    }
+
+    /// Workers which send no item or watermark with event time for `timeout` are idle, their watermarks are ignored
+    /// by receivers until they send again, so a worker without data doesn't hold back watermarks of the job; if all
+    /// workers are idle, the smallest of their watermarks is used; watermarks of workers are never ignored if it's not
+    /// set;
+    pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = Some(timeout);
+        self
+    }
+
+    pub fn get_job_id(&self) -> u64 {
+        self.job_id
+    }
+
+    pub fn get_parallel(&self) -> usize {
+        self.parallel
+    }
+
+    pub fn get_channel_capacity(&self) -> usize {
+        self.channel_capacity
+    }
+
+    pub fn get_result_capacity(&self) -> usize {
+        self.result_capacity
+    }
+
+    pub(crate) fn spawn<F>(&self, task: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        self.spawner.spawn_boxed(task.boxed())
+    }
+}
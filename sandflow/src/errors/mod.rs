@@ -1,3 +1,4 @@
+use std::any::Any;
 use std::error::Error;
 use std::fmt::{Display, Formatter};
 
@@ -10,6 +11,12 @@ pub enum FError {
     StrHint(String),
     ChSend(SendError),
     Unknown(Box<dyn Error + Send + Sync + 'static>),
+    /// A stage panicked, `worker` and `stage` are `None` if the source panicked;
+    Panic {
+        worker: Option<u32>,
+        stage: Option<u32>,
+        message: String,
+    },
 }
 
 impl Display for FError {
@@ -19,11 +26,24 @@ impl Display for FError {
             FError::StrHint(str) => f.write_str(str),
             FError::ChSend(e) => write!(f, "{}", e),
             FError::Unknown(e) => write!(f, "{}", e),
+            FError::Panic { worker: Some(worker), stage: Some(stage), message } => {
+                write!(f, "worker[{}]: stage({}) panicked: {}", worker, stage, message)
+            }
+            FError::Panic { message, .. } => write!(f, "source panicked: {}", message),
         }
     }
 }
 
-impl Error for FError {}
+impl Error for FError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            FError::SystemIO(e) => Some(e),
+            FError::ChSend(e) => Some(e),
+            FError::Unknown(e) => Some(e.as_ref()),
+            _ => None,
+        }
+    }
+}
 
 impl From<std::io::Error> for FError {
     fn from(e: std::io::Error) -> Self {
@@ -36,3 +56,99 @@ impl From<SendError> for FError {
         FError::ChSend(e)
     }
 }
+
+impl FError {
+    pub(crate) fn from_panic(worker: Option<u32>, stage: Option<u32>, payload: Box<dyn Any + Send>) -> Self {
+        let message = if let Some(msg) = payload.downcast_ref::<&'static str>() {
+            msg.to_string()
+        } else if let Some(msg) = payload.downcast_ref::<String>() {
+            msg.clone()
+        } else {
+            "unknown panic".to_owned()
+        };
+        FError::Panic { worker, stage, message }
+    }
+}
+
+/// Error of a job, with where it occurred, `worker` and `stage` are `None` if it occurred in the source, and `stage` is
+/// `None` if it is a dead letter;
+#[derive(Debug)]
+pub struct JobError {
+    job_id: u64,
+    worker: Option<u32>,
+    stage: Option<u32>,
+    operator: Option<&'static str>,
+    error: FError,
+    suppressed: Vec<JobError>,
+}
+
+impl JobError {
+    pub(crate) fn new(
+        job_id: u64, worker: Option<u32>, stage: Option<u32>, operator: Option<&'static str>, error: FError,
+    ) -> Self {
+        Self { job_id, worker, stage, operator, error, suppressed: Vec::new() }
+    }
+
+    pub fn job_id(&self) -> u64 {
+        self.job_id
+    }
+
+    pub fn worker_index(&self) -> Option<u32> {
+        self.worker
+    }
+
+    pub fn stage_id(&self) -> Option<u32> {
+        self.stage
+    }
+
+    /// Name of the operator which created the stage, e.g. `exchange`;
+    pub fn operator(&self) -> Option<&'static str> {
+        self.operator
+    }
+
+    pub fn error(&self) -> &FError {
+        &self.error
+    }
+
+    pub fn into_error(self) -> FError {
+        self.error
+    }
+
+    /// Errors occurred after this one, they are only kept if the job collects errors;
+    pub fn suppressed(&self) -> &[JobError] {
+        &self.suppressed
+    }
+
+    pub(crate) fn suppress(&mut self, errors: Vec<JobError>) {
+        self.suppressed.extend(errors);
+    }
+}
+
+impl Display for JobError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "job({}) ", self.job_id)?;
+        match (self.worker, self.stage) {
+            (Some(worker), Some(stage)) => write!(f, "worker[{}]: stage({})", worker, stage)?,
+            (Some(worker), None) => write!(f, "worker[{}]", worker)?,
+            _ => f.write_str("source")?,
+        }
+        if let Some(operator) = self.operator {
+            write!(f, " of {}", operator)?;
+        }
+        match &self.error {
+            // where it panicked is already written;
+            FError::Panic { message, .. } => write!(f, " panicked: {}", message)?,
+            error => write!(f, " failed: {}", error)?,
+        }
+        if !self.suppressed.is_empty() {
+            write!(f, " (and {} more errors)", self.suppressed.len())?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for JobError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.error)
+    }
+}
@@ -6,16 +6,21 @@ use std::pin::Pin;
 use std::rc::Rc;
 use std::sync::Arc;
 use std::task::{Context, Poll};
+use std::time::Duration;
 
+use futures::channel::mpsc::{Receiver, Sender};
 use futures::future::JoinAll;
 use sandflow_cluster::ServerId;
+use sandflow_executor::Spawner;
 
-use crate::channels::local::LocalChannel;
+use crate::config::JobConfig;
 use crate::errors::FError;
+use crate::stages::scope::LoopScope;
 use crate::stages::sink::LocalStageSink;
 use crate::stages::source::StageInput;
-use crate::stages::utils::ErrorHook;
+use crate::stages::utils::{ErrorHook, TaskGuard};
 use crate::stages::AsyncStage;
+use crate::streams::spill::MemoryBudget;
 use crate::SandData;
 
 #[derive(Clone)]
@@ -30,25 +35,43 @@ pub struct SandFlowBuilder {
     next_worker_index: usize,
     error_hook: Arc<ErrorHook>,
     servers: Arc<Vec<ServerId>>,
+    scope: Option<Arc<LoopScope>>,
+    spawner: Arc<dyn Spawner>,
+    memory_budget: Option<Arc<MemoryBudget>>,
+    channel_capacity: usize,
+    idle_timeout: Option<Duration>,
 }
 
 impl SandFlowBuilder {
     pub fn new(job_id: u64, parallel: usize) -> Self {
-        Self::with_servers(job_id, parallel, 0, Arc::new(vec![]))
+        Self::with_config(&JobConfig::new(job_id, parallel))
     }
 
-    pub fn with_servers(job_id: u64, parallel: usize, server_index: usize, servers: Arc<Vec<ServerId>>) -> Self {
+    pub fn with_config(config: &JobConfig) -> Self {
+        Self::with_servers(config, 0, Arc::new(vec![]))
+    }
+
+    pub fn with_servers(config: &JobConfig, server_index: usize, servers: Arc<Vec<ServerId>>) -> Self {
+        let memory_budget = config.memory_budget.map(|limit| {
+            let dir = config.spill_dir.clone().unwrap_or_else(std::env::temp_dir);
+            Arc::new(MemoryBudget::new(config.job_id, limit, dir))
+        });
         Self {
-            job_id,
-            local_peers: parallel,
+            job_id: config.job_id,
+            local_peers: config.parallel,
             worker_index: 0,
             server_index,
             stages: Rc::new(RefCell::new(Vec::new())),
             next_ch_index: Rc::new(RefCell::new(0)),
             alloc_channels: Rc::new(RefCell::new(Vec::new())),
             next_worker_index: 0,
-            error_hook: Arc::new(ErrorHook::new()),
+            error_hook: Arc::new(ErrorHook::new(config.job_id, config.max_errors)),
             servers,
+            scope: None,
+            spawner: config.spawner.clone(),
+            memory_budget,
+            channel_capacity: config.channel_capacity,
+            idle_timeout: config.idle_timeout,
         }
     }
 
@@ -70,6 +93,11 @@ impl SandFlowBuilder {
                 next_worker_index: 0,
                 error_hook: self.error_hook.clone(),
                 servers: self.servers.clone(),
+                scope: None,
+                spawner: self.spawner.clone(),
+                memory_budget: self.memory_budget.clone(),
+                channel_capacity: self.channel_capacity,
+                idle_timeout: self.idle_timeout,
             }
         } else {
             panic!("can't fork mirror from mirror;")
@@ -100,46 +128,136 @@ impl SandFlowBuilder {
         &self.error_hook
     }
 
+    /// Memory budget shared by all local workers of the job, `None` if states are never spilled;
+    pub fn get_memory_budget(&self) -> Option<&Arc<MemoryBudget>> {
+        self.memory_budget.as_ref()
+    }
+
+    /// The spawner which runs tasks of the job, it also provides timers;
+    pub fn get_spawner(&self) -> &Arc<dyn Spawner> {
+        &self.spawner
+    }
+
     pub fn add_stage<F>(&self, stage: F)
+    where
+        F: Future<Output = Result<(), FError>> + Send + 'static,
+    {
+        self.add_stage_with_name(None, stage)
+    }
+
+    /// Same as `add_stage`, the name of the operator which creates the stage is reported with its error;
+    pub fn add_named_stage<F>(&self, name: &'static str, stage: F)
+    where
+        F: Future<Output = Result<(), FError>> + Send + 'static,
+    {
+        self.add_stage_with_name(Some(name), stage)
+    }
+
+    fn add_stage_with_name<F>(&self, name: Option<&'static str>, stage: F)
     where
         F: Future<Output = Result<(), FError>> + Send + 'static,
     {
         let mut stages_borrow = self.stages.borrow_mut();
         let next_stage_id = stages_borrow.len() as u32;
-        stages_borrow.push(AsyncStage::new(self.worker_index as u32, next_stage_id, stage, &self.error_hook));
+        stages_borrow.push(AsyncStage::new(self.worker_index as u32, next_stage_id, name, stage, &self.error_hook));
+    }
+
+    /// Create a builder of the loop body, channels allocated by it are tracked by the loop scope;
+    pub fn in_scope(&self, scope: Arc<LoopScope>) -> Self {
+        assert!(self.scope.is_none(), "nested iteration is not supported;");
+        let mut fb = self.clone();
+        fb.scope = Some(scope);
+        fb
+    }
+
+    /// Default capacity of channels allocated by the builder;
+    pub fn get_channel_capacity(&self) -> usize {
+        self.channel_capacity
+    }
+
+    /// Timeout after which a worker sending no item with event time is idle, see `JobConfig::idle_timeout`;
+    pub fn get_idle_timeout(&self) -> Option<Duration> {
+        self.idle_timeout
     }
 
     pub fn alloc_local<T: SandData>(&self) -> (Vec<LocalStageSink<T>>, StageInput<T>) {
-        assert!(self.local_peers > 1, "local peers should be larger than 1");
+        self.alloc_local_with_capacity(self.channel_capacity)
+    }
+
+    /// Same as `alloc_local`, but channels are created with `capacity` instead of the default capacity; a job of one
+    /// worker gets one channel which the worker sends to itself;
+    pub fn alloc_local_with_capacity<T: SandData>(&self, capacity: usize) -> (Vec<LocalStageSink<T>>, StageInput<T>) {
+        let (senders, receiver) = self
+            .alloc_peers(|peers| crate::channels::local::alloc::<T>(peers, capacity))
+            .take();
+        let mut sinks = Vec::with_capacity(self.local_peers);
+        for t in senders {
+            sinks.push(self.new_sink(t));
+        }
+        (sinks, self.new_input(receiver))
+    }
+
+    /// Allocate a channel between stages of this worker;
+    pub fn alloc_channel<T: SandData>(&self) -> (LocalStageSink<T>, StageInput<T>) {
+        let (tx, rx) = futures::channel::mpsc::channel::<T>(self.channel_capacity);
+        (self.new_sink(tx), self.new_input(rx))
+    }
+
+    /// Allocate a value shared by all local peers, it is created by the first worker;
+    pub fn alloc_shared<S, F>(&self, create: F) -> Arc<S>
+    where
+        S: SandData,
+        F: FnOnce() -> S,
+    {
+        self.alloc_peers(|peers| {
+            let shared = Arc::new(create());
+            (0..peers).map(|_| shared.clone()).collect()
+        })
+    }
+
+    /// Allocate one value for each local peer, values are created by the first worker and taken by other workers
+    /// in order, so all workers must allocate in the same order;
+    fn alloc_peers<T, F>(&self, alloc: F) -> T
+    where
+        T: 'static,
+        F: FnOnce(usize) -> VecDeque<T>,
+    {
         let mut next_ch_index = self.next_ch_index.borrow_mut();
         let ch_index = *next_ch_index;
-        let (senders, receiver) = if self.worker_index == 0 {
-            let mut channels = crate::channels::local::alloc::<T>(self.local_peers, 1024);
-            assert_eq!(channels.len(), self.local_peers);
-            let (t, r) = channels.pop_front().unwrap().take();
-            let mut queue = VecDeque::with_capacity(channels.len());
-            for ch in channels {
-                queue.push_back(Box::new(ch) as Box<dyn Any>);
+        let value = if self.worker_index == 0 {
+            let mut values = alloc(self.local_peers);
+            assert_eq!(values.len(), self.local_peers);
+            let first = values.pop_front().unwrap();
+            let mut queue = VecDeque::with_capacity(values.len());
+            for v in values {
+                queue.push_back(Box::new(v) as Box<dyn Any>);
             }
             let mut alloc_chs = self.alloc_channels.borrow_mut();
             assert_eq!(alloc_chs.len(), ch_index);
             alloc_chs.push(queue);
-            (t, r)
+            first
         } else {
             let mut alloc_chs = self.alloc_channels.borrow_mut();
             assert!(alloc_chs.len() > ch_index);
-            let ch_any = alloc_chs[ch_index].pop_front().expect("local channel lost;");
-            ch_any
-                .downcast::<LocalChannel<T>>()
-                .expect("type cast fail;")
-                .take()
+            let v_any = alloc_chs[ch_index].pop_front().expect("local channel lost;");
+            *v_any.downcast::<T>().expect("type cast fail;")
         };
         *next_ch_index += 1;
-        let mut sinks = Vec::with_capacity(self.local_peers);
-        for t in senders {
-            sinks.push(LocalStageSink::new(t));
+        value
+    }
+
+    fn new_sink<T>(&self, sender: Sender<T>) -> LocalStageSink<T> {
+        match self.scope.as_ref() {
+            Some(scope) => LocalStageSink::with_scope(sender, scope.clone()),
+            None => LocalStageSink::new(sender),
+        }
+    }
+
+    fn new_input<T>(&self, receiver: Receiver<T>) -> StageInput<T> {
+        match self.scope.as_ref() {
+            Some(scope) => StageInput::with_scope(receiver, scope.clone()),
+            None => StageInput::new(receiver),
         }
-        (sinks, StageInput::new(receiver))
     }
 
     pub fn build(self) -> SandFlow {
@@ -151,13 +269,16 @@ impl SandFlowBuilder {
         }
 
         let task = futures::future::join_all(stages_final);
-        SandFlow { local_peers: self.local_peers, worker_index: self.worker_index, task }
+        let guard = self.error_hook.new_task();
+        SandFlow { local_peers: self.local_peers, worker_index: self.worker_index, error_hook: self.error_hook, guard, task }
     }
 }
 
 pub struct SandFlow {
     local_peers: usize,
     worker_index: usize,
+    error_hook: Arc<ErrorHook>,
+    guard: TaskGuard,
     task: JoinAll<AsyncStage>,
 }
 
@@ -202,6 +323,12 @@ impl Future for SandFlow {
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         let this = self.get_mut();
+        this.guard.register(cx.waker());
+        if this.error_hook.is_aborted() {
+            // stages left are dropped with the flow;
+            debug!("worker[{}]: abort as job is aborted;", this.worker_index);
+            return Poll::Ready(());
+        }
         let _guard = WorkerIndexGuard::new(this.worker_index);
         Pin::new(&mut this.task).poll(cx).map(|_| ())
     }
@@ -1,17 +1,32 @@
 #[macro_use]
 extern crate log;
 
+use std::future::Future;
+
+pub use config::JobConfig;
+pub use errors::{FError, JobError};
 pub use flow::worker_index;
-use futures::Stream;
+use futures::channel::mpsc::Receiver;
+use futures::future::BoxFuture;
+use futures::{FutureExt, Stream};
+#[cfg(feature = "tokio")]
+pub use sandflow_executor::TokioSpawner;
+pub use sandflow_executor::{install, ExecutorConfig, GlobalSpawner, SandFlowExecutor, Spawner};
+pub use streams::event_time::Timed;
+pub use streams::on_error::{dead_letters, DeadLetterSink, DeadLetters, ErrorPolicy};
+pub use streams::pstream::{KeyedStream, TimedStream};
+pub use streams::result_stream::{JobHandle, JoinJob, ResultStream};
+pub use streams::retry::RetryPolicy;
+pub use streams::spill::Spill;
+pub use streams::state::{KeyContext, ListState, MapState, ValueState};
+pub use streams::window::{TimeWindow, Window, WindowResult};
 
-use crate::errors::FError;
 use crate::flow::SandFlowBuilder;
 use crate::stages::sink::select::SelectSink;
 use crate::stages::sink::LocalStageSink;
 use crate::stages::source::{SourceStage, StageInput};
 use crate::stages::utils::ErrorHook;
 use crate::streams::pstream::{InputStream, PStream};
-use crate::streams::result_stream::ResultStream;
 use crate::streams::StreamExtend;
 
 pub trait SandData: Send + Sync + 'static {}
@@ -19,10 +34,12 @@ pub trait SandData: Send + Sync + 'static {}
 impl<T> SandData for T where T: Send + Sync + 'static {}
 
 mod channels;
+mod config;
 mod errors;
 mod flow;
 mod stages;
 mod streams;
+#[cfg(test)]
 mod test;
 
 const DEFAULT_PARALLEL: u32 = 2;
@@ -50,23 +67,103 @@ where
     So: Stream<Item = Result<DO, FError>> + Send + 'static,
     F: Fn() -> FF,
     FF: FnOnce(InputStream<DI>) -> PStream<So>,
+{
+    spawn_job_with(JobConfig::new(job_id, parallel), source, func)
+}
+
+pub fn spawn_job_with<Si, So, DI, DO, F, FF>(config: JobConfig, source: Si, func: F) -> ResultStream<DO>
+where
+    DI: SandData,
+    DO: SandData,
+    Si: Stream<Item = Result<DI, FError>> + Send + Unpin + 'static,
+    So: Stream<Item = Result<DO, FError>> + Send + 'static,
+    F: Fn() -> FF,
+    FF: FnOnce(InputStream<DI>) -> PStream<So>,
+{
+    let (source_fut, rxs) = dispatch(&config, source);
+    let mut rxs = rxs.into_iter();
+    launch(config, vec![source_fut.boxed()], |fb| {
+        let st = PStream::new(fb, StageInput::new(rxs.next().expect("input lost;")));
+        let progress = func();
+        progress(st)
+    })
+}
+
+/// Same as `spawn_job`, but the job has two sources, each worker gets an input stream of each source;
+pub fn spawn_job2<Si1, Si2, So, DI1, DI2, DO, F, FF>(
+    job_id: u64, parallel: usize, source1: Si1, source2: Si2, func: F,
+) -> ResultStream<DO>
+where
+    DI1: SandData,
+    DI2: SandData,
+    DO: SandData,
+    Si1: Stream<Item = Result<DI1, FError>> + Send + Unpin + 'static,
+    Si2: Stream<Item = Result<DI2, FError>> + Send + Unpin + 'static,
+    So: Stream<Item = Result<DO, FError>> + Send + 'static,
+    F: Fn() -> FF,
+    FF: FnOnce(InputStream<DI1>, InputStream<DI2>) -> PStream<So>,
+{
+    spawn_job2_with(JobConfig::new(job_id, parallel), source1, source2, func)
+}
+
+pub fn spawn_job2_with<Si1, Si2, So, DI1, DI2, DO, F, FF>(
+    config: JobConfig, source1: Si1, source2: Si2, func: F,
+) -> ResultStream<DO>
+where
+    DI1: SandData,
+    DI2: SandData,
+    DO: SandData,
+    Si1: Stream<Item = Result<DI1, FError>> + Send + Unpin + 'static,
+    Si2: Stream<Item = Result<DI2, FError>> + Send + Unpin + 'static,
+    So: Stream<Item = Result<DO, FError>> + Send + 'static,
+    F: Fn() -> FF,
+    FF: FnOnce(InputStream<DI1>, InputStream<DI2>) -> PStream<So>,
+{
+    let (source_fut1, rxs1) = dispatch(&config, source1);
+    let (source_fut2, rxs2) = dispatch(&config, source2);
+    let mut rxs = rxs1.into_iter().zip(rxs2);
+    launch(config, vec![source_fut1.boxed(), source_fut2.boxed()], |fb| {
+        let (r1, r2) = rxs.next().expect("input lost;");
+        let st1 = PStream::new(fb.clone(), StageInput::new(r1));
+        let st2 = PStream::new(fb, StageInput::new(r2));
+        let progress = func();
+        progress(st1, st2)
+    })
+}
+
+/// Dispatch items of the source to workers in a round-robin manner;
+fn dispatch<Si, DI>(config: &JobConfig, source: Si) -> (impl Future<Output = Result<(), FError>> + Send, Vec<Receiver<DI>>)
+where
+    DI: SandData,
+    Si: Stream<Item = Result<DI, FError>> + Send + Unpin + 'static,
 {
     let mut txs = Vec::new();
     let mut rxs = Vec::new();
 
-    for _ in 0..parallel {
-        let (tx, rx) = futures::channel::mpsc::channel::<DI>(1024);
+    for _ in 0..config.parallel {
+        let (tx, rx) = futures::channel::mpsc::channel::<DI>(config.channel_capacity);
         txs.push(LocalStageSink::<DI>::new(tx));
         rxs.push(rx);
     }
 
-    let source_fut = source.select_forward(SelectSink::round_select(txs));
-    let (tx, rx) = futures::channel::mpsc::channel::<DO>(1024);
+    (source.select_forward(SelectSink::round_select(txs)), rxs)
+}
+
+fn launch<So, DO, P>(
+    config: JobConfig, sources: Vec<BoxFuture<'static, Result<(), FError>>>, mut progress: P,
+) -> ResultStream<DO>
+where
+    DO: SandData,
+    So: Stream<Item = Result<DO, FError>> + Send + 'static,
+    P: FnMut(SandFlowBuilder) -> PStream<So>,
+{
+    let (job_id, parallel) = (config.job_id, config.parallel);
+    let (tx, rx) = futures::channel::mpsc::channel::<DO>(config.result_capacity);
 
-    let mut primary = SandFlowBuilder::new(job_id, parallel);
+    let mut primary = SandFlowBuilder::with_config(&config);
     let mut mirrors = Vec::with_capacity(parallel - 1);
 
-    for (i, r) in rxs.into_iter().enumerate() {
+    for i in 0..parallel {
         let fb = if i == 0 {
             primary.clone()
         } else {
@@ -75,20 +172,20 @@ where
             mirror
         };
 
-        let st = PStream::new(fb.clone(), StageInput::new(r));
-        let progress = func();
-        let last = progress(st);
+        let last = progress(fb.clone());
         let sink = LocalStageSink::<DO>::new(tx.clone());
         let last_fut = last.forward(sink);
-        fb.add_stage(last_fut);
+        fb.add_named_stage("output", last_fut);
     }
 
     let error_hook = primary.get_error_hook().clone();
-    sandflow_executor::spawn(SourceStage::new(job_id, error_hook.clone(), source_fut));
-    sandflow_executor::spawn(primary.build());
+    for source_fut in sources {
+        config.spawn(SourceStage::new(job_id, error_hook.clone(), source_fut));
+    }
+    config.spawn(primary.build());
     for m in mirrors {
-        sandflow_executor::spawn(m.build());
+        config.spawn(m.build());
     }
 
-    ResultStream::new(error_hook, rx)
+    ResultStream::new(job_id, error_hook, rx)
 }
@@ -1,4 +1,5 @@
 use std::future::Future;
+use std::panic::AssertUnwindSafe;
 use std::pin::Pin;
 use std::sync::Arc;
 use std::task::{Context, Poll};
@@ -14,6 +15,7 @@ pin_project! {
     pub struct AsyncStage {
         worker_index: u32,
         stage_id: u32,
+        name: Option<&'static str>,
         error_hook: Arc<ErrorHook>,
         #[pin]
         task: BoxFuture<'static, Result<(), FError>>
@@ -21,11 +23,11 @@ pin_project! {
 }
 
 impl AsyncStage {
-    pub fn new<F>(worker_index: u32, stage_id: u32, task: F, error_hook: &Arc<ErrorHook>) -> Self
+    pub fn new<F>(worker_index: u32, stage_id: u32, name: Option<&'static str>, task: F, error_hook: &Arc<ErrorHook>) -> Self
     where
         F: Future<Output = Result<(), FError>> + Send + 'static,
     {
-        Self { worker_index, stage_id, task: Box::pin(task), error_hook: error_hook.clone() }
+        Self { worker_index, stage_id, name, task: Box::pin(task), error_hook: error_hook.clone() }
     }
 }
 
@@ -38,21 +40,34 @@ impl Future for AsyncStage {
             return Poll::Ready(());
         }
 
+        if self.error_hook.is_cancelled() {
+            debug!("worker[{}]: abort stage({}) as job is cancelled;", self.worker_index, self.stage_id);
+            return Poll::Ready(());
+        }
+
         let this = self.project();
-        match ready!(this.task.poll(cx)) {
+        let mut task = this.task;
+        // panics of user functions are caught here, so they abort the job instead of the thread;
+        let polled = match std::panic::catch_unwind(AssertUnwindSafe(|| task.as_mut().poll(cx))) {
+            Ok(polled) => polled,
+            Err(payload) => Poll::Ready(Err(FError::from_panic(Some(*this.worker_index), Some(*this.stage_id), payload))),
+        };
+        match ready!(polled) {
             Ok(()) => {
                 debug!("worker[{}]: stage({}) is finished;", this.worker_index, this.stage_id);
                 Poll::Ready(())
             }
             Err(e) => {
                 error!("worker[{}]: stage({}) executed fail: {};", this.worker_index, this.stage_id, e);
-                this.error_hook.set_error(e);
+                this.error_hook
+                    .set_error(Some(*this.worker_index), Some(*this.stage_id), *this.name, e);
                 Poll::Ready(())
             }
         }
     }
 }
 
+pub(crate) mod scope;
 pub(crate) mod sink;
 pub(crate) mod source;
 pub(crate) mod utils;
@@ -0,0 +1,175 @@
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::Waker;
+
+use futures::task::AtomicWaker;
+
+/// The round value after the loop is finished;
+pub const FINISHED_ROUND: u32 = u32::MAX;
+
+/// Tracks progress of an iteration shared by all local peers.
+///
+/// Every channel allocated inside the loop body counts items sent and received, and every input of the loop body
+/// records if its consumer is still busy on the last item it received. The loop is quiescent when no head is emitting,
+/// no input is busy and no item is left in channels; a round is finished when the loop is quiescent.
+pub struct LoopScope {
+    peers: usize,
+    max_rounds: usize,
+    // (round << 32) | count of heads still emitting items of the round;
+    state: AtomicU64,
+    sent: AtomicUsize,
+    received: AtomicUsize,
+    busy_inputs: AtomicUsize,
+    next_round_items: AtomicUsize,
+    // updates are wrapped by `begin` and `end`, so counters can be read as a consistent snapshot;
+    begin: AtomicUsize,
+    end: AtomicUsize,
+    heads: Vec<AtomicWaker>,
+}
+
+impl LoopScope {
+    pub fn new(peers: usize, max_rounds: usize) -> Self {
+        let mut heads = Vec::with_capacity(peers);
+        for _ in 0..peers {
+            heads.push(AtomicWaker::new());
+        }
+        Self {
+            peers,
+            max_rounds,
+            state: AtomicU64::new(peers as u64),
+            sent: AtomicUsize::new(0),
+            received: AtomicUsize::new(0),
+            busy_inputs: AtomicUsize::new(0),
+            next_round_items: AtomicUsize::new(0),
+            begin: AtomicUsize::new(0),
+            end: AtomicUsize::new(0),
+            heads,
+        }
+    }
+
+    #[inline]
+    fn update<F: FnOnce()>(&self, func: F) {
+        self.begin.fetch_add(1, Ordering::SeqCst);
+        func();
+        self.end.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Called before an item is sent into a channel of the loop;
+    pub fn on_send(&self) {
+        self.update(|| {
+            self.sent.fetch_add(1, Ordering::SeqCst);
+        });
+    }
+
+    /// Called after an item is received from a channel of the loop, `is_busy` tells if the input is already busy;
+    pub fn on_receive(&self, is_busy: bool) {
+        self.update(|| {
+            if !is_busy {
+                self.busy_inputs.fetch_add(1, Ordering::SeqCst);
+            }
+            self.received.fetch_add(1, Ordering::SeqCst);
+        });
+    }
+
+    /// Called when the consumer of a busy input asks for more but nothing is available;
+    pub fn on_idle(&self) {
+        self.update(|| {
+            self.busy_inputs.fetch_sub(1, Ordering::SeqCst);
+        });
+        self.notify();
+    }
+
+    pub fn add_next_round_items(&self, size: usize) {
+        self.update(|| {
+            self.next_round_items.fetch_add(size, Ordering::SeqCst);
+        });
+    }
+
+    pub fn take_next_round_items(&self, size: usize) {
+        self.update(|| {
+            self.next_round_items.fetch_sub(size, Ordering::SeqCst);
+        });
+    }
+
+    /// Called when a head has emitted all items of current round;
+    pub fn finish_emit(&self) {
+        self.update(|| {
+            self.state.fetch_sub(1, Ordering::SeqCst);
+        });
+        self.notify();
+    }
+
+    #[inline]
+    pub fn round(&self) -> u32 {
+        (self.state.load(Ordering::SeqCst) >> 32) as u32
+    }
+
+    /// Try to finish the `round` if the loop is quiescent, return true if the round is already finished;
+    pub fn try_advance(&self, round: u32) -> bool {
+        let current = (round as u64) << 32;
+        let end = self.end.load(Ordering::SeqCst);
+        let is_quiescent = self.state.load(Ordering::SeqCst) == current
+            && self.busy_inputs.load(Ordering::SeqCst) == 0
+            && self.sent.load(Ordering::SeqCst) == self.received.load(Ordering::SeqCst);
+        let next_round_items = self.next_round_items.load(Ordering::SeqCst);
+        if !is_quiescent || self.begin.load(Ordering::SeqCst) != end {
+            return self.round() != round;
+        }
+
+        let next = if next_round_items == 0 {
+            debug!("iteration finished after {} rounds;", round as usize + 1);
+            (FINISHED_ROUND as u64) << 32
+        } else if round as usize + 1 >= self.max_rounds {
+            warn!("iteration reached max rounds {}, {} items are dropped;", self.max_rounds, next_round_items);
+            (FINISHED_ROUND as u64) << 32
+        } else {
+            ((round as u64 + 1) << 32) | self.peers as u64
+        };
+        let _ = self
+            .state
+            .compare_exchange(current, next, Ordering::SeqCst, Ordering::SeqCst);
+        self.notify();
+        true
+    }
+
+    pub fn register(&self, worker_index: usize, waker: &Waker) {
+        self.heads[worker_index].register(waker);
+    }
+
+    fn notify(&self) {
+        for head in self.heads.iter() {
+            head.wake();
+        }
+    }
+}
+
+/// Records if the consumer of a loop input is busy;
+pub struct InputProbe {
+    scope: Arc<LoopScope>,
+    is_busy: bool,
+}
+
+impl InputProbe {
+    pub fn new(scope: Arc<LoopScope>) -> Self {
+        Self { scope, is_busy: false }
+    }
+
+    #[inline]
+    pub fn on_next(&mut self, has_item: bool) {
+        if has_item {
+            self.scope.on_receive(self.is_busy);
+            self.is_busy = true;
+        } else if self.is_busy {
+            self.is_busy = false;
+            self.scope.on_idle();
+        }
+    }
+}
+
+impl Drop for InputProbe {
+    fn drop(&mut self) {
+        if self.is_busy {
+            self.scope.on_idle();
+        }
+    }
+}
@@ -0,0 +1,78 @@
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures::{ready, Sink};
+use pin_project_lite::pin_project;
+
+use crate::stages::sink::{TagSink, TrySink};
+
+pin_project! {
+    /// Sink a copy of each item into every sink;
+    pub struct BroadcastSink<Si> {
+        sinks: Vec<TagSink<Si>>,
+        // index of the next sink which hasn't received the current item;
+        cursor: usize,
+    }
+}
+
+impl<Si> BroadcastSink<Si> {
+    pub fn new(sinks: Vec<Si>) -> Self {
+        assert!(!sinks.is_empty(), "broadcast to no sink;");
+        let mut tag_sinks = Vec::with_capacity(sinks.len());
+        for s in sinks {
+            tag_sinks.push(TagSink::new(s));
+        }
+        Self { sinks: tag_sinks, cursor: 0 }
+    }
+}
+
+impl<Si, Item> TrySink<Item> for BroadcastSink<Si>
+where
+    Si: Sink<Item>,
+    Item: Clone,
+{
+    type Error = Si::Error;
+
+    fn try_sink(self: Pin<&mut Self>, item: Item, cx: &mut Context<'_>) -> Result<Option<Item>, Self::Error> {
+        let this = self.project();
+        let last = this.sinks.len() - 1;
+        // sinks before cursor already have a copy of the item, it will be given back and retried from cursor if
+        // any sink isn't ready;
+        while *this.cursor < last {
+            let sink = unsafe { Pin::new_unchecked(&mut this.sinks[*this.cursor]) };
+            if sink.try_sink(item.clone(), cx)?.is_some() {
+                return Ok(Some(item));
+            }
+            *this.cursor += 1;
+        }
+
+        let sink = unsafe { Pin::new_unchecked(&mut this.sinks[last]) };
+        let pending = sink.try_sink(item, cx)?;
+        if pending.is_none() {
+            *this.cursor = 0;
+        }
+        Ok(pending)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = self.project();
+        for sink in this.sinks.iter_mut() {
+            let pinned = unsafe { Pin::new_unchecked(sink) };
+            if let Err(e) = ready!(pinned.poll_flush(cx)) {
+                return Poll::Ready(Err(e));
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = self.project();
+        for sink in this.sinks.iter_mut() {
+            let pinned = unsafe { Pin::new_unchecked(sink) };
+            if let Err(e) = ready!(pinned.poll_close(cx)) {
+                return Poll::Ready(Err(e));
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+}
@@ -1,9 +1,12 @@
 use std::pin::Pin;
+use std::sync::Arc;
 use std::task::{Context, Poll};
 
 use futures::channel::mpsc::Sender;
 use futures::{ready, Sink};
+use pin_project_lite::pin_project;
 
+use crate::stages::scope::LoopScope;
 use crate::FError;
 
 pub trait TrySink<T> {
@@ -20,11 +23,25 @@ pub trait TrySink<T> {
 }
 
 /// Sink data between local stages;
-pub struct LocalStageSink<T>(Sender<T>);
+pub struct LocalStageSink<T> {
+    sender: Sender<T>,
+    scope: Option<Arc<LoopScope>>,
+}
 
 impl<T> LocalStageSink<T> {
     pub fn new(sender: Sender<T>) -> Self {
-        LocalStageSink(sender)
+        LocalStageSink { sender, scope: None }
+    }
+
+    /// Create a sink inside a loop, which reports items sent to the loop scope;
+    pub fn with_scope(sender: Sender<T>, scope: Arc<LoopScope>) -> Self {
+        LocalStageSink { sender, scope: Some(scope) }
+    }
+}
+
+impl<T> Clone for LocalStageSink<T> {
+    fn clone(&self) -> Self {
+        LocalStageSink { sender: self.sender.clone(), scope: self.scope.clone() }
     }
 }
 
@@ -32,32 +49,103 @@ impl<T> Sink<T> for LocalStageSink<T> {
     type Error = FError;
 
     fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        match ready!(self.get_mut().0.poll_ready(cx)) {
+        match ready!(self.get_mut().sender.poll_ready(cx)) {
             Ok(()) => Poll::Ready(Ok(())),
             Err(e) => Poll::Ready(Err(FError::ChSend(e))),
         }
     }
 
     fn start_send(self: Pin<&mut Self>, item: T) -> Result<(), Self::Error> {
-        match self.get_mut().0.start_send(item) {
+        let this = self.get_mut();
+        if let Some(scope) = this.scope.as_ref() {
+            scope.on_send();
+        }
+        match this.sender.start_send(item) {
             Ok(_) => Ok(()),
             Err(e) => Err(FError::ChSend(e)),
         }
     }
 
     fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        match ready!(Pin::new(&mut self.get_mut().0).poll_flush(cx)) {
+        match ready!(Pin::new(&mut self.get_mut().sender).poll_flush(cx)) {
             Ok(_) => Poll::Ready(Ok(())),
             Err(e) => Poll::Ready(Err(FError::ChSend(e))),
         }
     }
 
     fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        match ready!(Pin::new(&mut self.get_mut().0).poll_close(cx)) {
+        match ready!(Pin::new(&mut self.get_mut().sender).poll_close(cx)) {
             Ok(_) => Poll::Ready(Ok(())),
             Err(e) => Poll::Ready(Err(FError::ChSend(e))),
         }
     }
 }
 
+pin_project! {
+    pub(crate) struct TagSink<Si> {
+        #[pin]
+        sink: Si,
+        is_dirty: bool,
+        is_closed: bool,
+    }
+}
+
+impl<Si> TagSink<Si> {
+    pub(crate) fn new(sink: Si) -> Self {
+        Self { sink, is_dirty: false, is_closed: false }
+    }
+}
+
+impl<Si, T> TrySink<T> for TagSink<Si>
+where
+    Si: Sink<T>,
+{
+    type Error = Si::Error;
+
+    fn try_sink(self: Pin<&mut Self>, item: T, cx: &mut Context<'_>) -> Result<Option<T>, Self::Error> {
+        let mut this = self.project();
+        match this.sink.as_mut().poll_ready(cx) {
+            Poll::Ready(Ok(_)) => {
+                this.sink.as_mut().start_send(item)?;
+                *this.is_dirty |= true;
+                Ok(None)
+            }
+            Poll::Ready(Err(e)) => Err(e),
+            Poll::Pending => Ok(Some(item)),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = self.project();
+        if *this.is_dirty {
+            match ready!(this.sink.poll_flush(cx)) {
+                Ok(_) => {
+                    *this.is_dirty = false;
+                    Poll::Ready(Ok(()))
+                }
+                Err(e) => Poll::Ready(Err(e)),
+            }
+        } else {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = self.project();
+        if !*this.is_closed {
+            match ready!(this.sink.poll_close(cx)) {
+                Ok(_) => {
+                    *this.is_closed = true;
+                    Poll::Ready(Ok(()))
+                }
+                Err(e) => Poll::Ready(Err(e)),
+            }
+        } else {
+            Poll::Ready(Ok(()))
+        }
+    }
+}
+
+pub(crate) mod broadcast;
 pub(crate) mod select;
+pub(crate) mod watermark;
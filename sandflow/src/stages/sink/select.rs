@@ -4,7 +4,7 @@ use std::task::{Context, Poll};
 use futures::{ready, Sink};
 use pin_project_lite::pin_project;
 
-use crate::stages::sink::TrySink;
+use crate::stages::sink::{TagSink, TrySink};
 
 pub trait Selector<T> {
     /// Select a sink according to the item, return the id(index) which specific a sink;
@@ -36,71 +36,6 @@ impl<T> Selector<T> for RoundSelector<T> {
     }
 }
 
-pin_project! {
-    struct TagSink<Si> {
-        #[pin]
-        sink: Si,
-        is_dirty: bool,
-        is_closed: bool,
-    }
-}
-
-impl<Si> TagSink<Si> {
-    fn new(sink: Si) -> Self {
-        Self { sink, is_dirty: false, is_closed: false }
-    }
-}
-
-impl<Si, T> TrySink<T> for TagSink<Si>
-where
-    Si: Sink<T>,
-{
-    type Error = Si::Error;
-
-    fn try_sink(self: Pin<&mut Self>, item: T, cx: &mut Context<'_>) -> Result<Option<T>, Self::Error> {
-        let mut this = self.project();
-        match this.sink.as_mut().poll_ready(cx) {
-            Poll::Ready(Ok(_)) => {
-                this.sink.as_mut().start_send(item)?;
-                *this.is_dirty |= true;
-                Ok(None)
-            }
-            Poll::Ready(Err(e)) => Err(e),
-            Poll::Pending => Ok(Some(item)),
-        }
-    }
-
-    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        let this = self.project();
-        if *this.is_dirty {
-            match ready!(this.sink.poll_flush(cx)) {
-                Ok(_) => {
-                    *this.is_dirty = false;
-                    Poll::Ready(Ok(()))
-                }
-                Err(e) => Poll::Ready(Err(e)),
-            }
-        } else {
-            Poll::Ready(Ok(()))
-        }
-    }
-
-    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        let this = self.project();
-        if !*this.is_closed {
-            match ready!(this.sink.poll_close(cx)) {
-                Ok(_) => {
-                    *this.is_closed = true;
-                    Poll::Ready(Ok(()))
-                }
-                Err(e) => Poll::Ready(Err(e)),
-            }
-        } else {
-            Poll::Ready(Ok(()))
-        }
-    }
-}
-
 pin_project! {
     pub struct SelectSink<Si, T> {
         sinks: Vec<TagSink<Si>>,
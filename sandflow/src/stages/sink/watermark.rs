@@ -0,0 +1,85 @@
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures::{ready, Sink};
+use pin_project_lite::pin_project;
+
+use crate::stages::sink::{TagSink, TrySink};
+use crate::streams::event_time::Timed;
+
+pin_project! {
+    /// Sink each item into the sink selected by `route`, and a copy of each watermark into every sink, items are
+    /// tagged with the index of the sender, so receivers can track watermarks of each sender;
+    pub struct WatermarkSink<Si, R> {
+        sinks: Vec<TagSink<Si>>,
+        route: R,
+        sender_index: u32,
+        // index of the next sink which hasn't received the current watermark;
+        cursor: usize,
+    }
+}
+
+impl<Si, R> WatermarkSink<Si, R> {
+    pub fn new(sender_index: u32, sinks: Vec<Si>, route: R) -> Self {
+        assert!(!sinks.is_empty(), "route to no sink;");
+        let sinks = sinks.into_iter().map(TagSink::new).collect();
+        Self { sinks, route, sender_index, cursor: 0 }
+    }
+}
+
+impl<Si, R, T> TrySink<Timed<T>> for WatermarkSink<Si, R>
+where
+    Si: Sink<(u32, Timed<T>)>,
+    R: FnMut(&T) -> u64,
+{
+    type Error = Si::Error;
+
+    fn try_sink(self: Pin<&mut Self>, item: Timed<T>, cx: &mut Context<'_>) -> Result<Option<Timed<T>>, Self::Error> {
+        let this = self.project();
+        let sender_index = *this.sender_index;
+        match item {
+            Timed::Item(ts, item) => {
+                let index = (this.route)(&item) % this.sinks.len() as u64;
+                let sink = unsafe { Pin::new_unchecked(&mut this.sinks[index as usize]) };
+                let pending = sink.try_sink((sender_index, Timed::Item(ts, item)), cx)?;
+                Ok(pending.map(|(_, item)| item))
+            }
+            Timed::Watermark(watermark) => {
+                while *this.cursor < this.sinks.len() {
+                    let sink = unsafe { Pin::new_unchecked(&mut this.sinks[*this.cursor]) };
+                    if sink
+                        .try_sink((sender_index, Timed::Watermark(watermark)), cx)?
+                        .is_some()
+                    {
+                        return Ok(Some(Timed::Watermark(watermark)));
+                    }
+                    *this.cursor += 1;
+                }
+                *this.cursor = 0;
+                Ok(None)
+            }
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = self.project();
+        for sink in this.sinks.iter_mut() {
+            let pinned = unsafe { Pin::new_unchecked(sink) };
+            if let Err(e) = ready!(TrySink::<(u32, Timed<T>)>::poll_flush(pinned, cx)) {
+                return Poll::Ready(Err(e));
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = self.project();
+        for sink in this.sinks.iter_mut() {
+            let pinned = unsafe { Pin::new_unchecked(sink) };
+            if let Err(e) = ready!(TrySink::<(u32, Timed<T>)>::poll_close(pinned, cx)) {
+                return Poll::Ready(Err(e));
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+}
@@ -1,4 +1,5 @@
 use std::future::Future;
+use std::panic::AssertUnwindSafe;
 use std::pin::Pin;
 use std::sync::Arc;
 use std::task::{Context, Poll};
@@ -7,13 +8,15 @@ use futures::channel::mpsc::Receiver;
 use futures::{ready, Stream};
 use pin_project_lite::pin_project;
 
-use crate::stages::utils::ErrorHook;
+use crate::stages::scope::{InputProbe, LoopScope};
+use crate::stages::utils::{ErrorHook, TaskGuard};
 use crate::FError;
 
 pin_project! {
     pub struct SourceStage<F> {
         job_id: u64,
         error_hook: Arc<ErrorHook>,
+        guard: TaskGuard,
         #[pin]
         task: F
     }
@@ -21,7 +24,8 @@ pin_project! {
 
 impl<F> SourceStage<F> {
     pub fn new(job_id: u64, error_hook: Arc<ErrorHook>, task: F) -> Self {
-        Self { job_id, error_hook, task }
+        let guard = error_hook.new_task();
+        Self { job_id, error_hook, guard, task }
     }
 }
 
@@ -32,30 +36,50 @@ where
     type Output = ();
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        if self.error_hook.has_error() {
+        self.guard.register(cx.waker());
+        if self.error_hook.is_aborted() {
+            debug!("source of job({}) is aborted;", self.job_id);
+            return Poll::Ready(());
+        }
+
+        if self.error_hook.is_stopped() {
+            debug!("source of job({}) is stopped;", self.job_id);
             return Poll::Ready(());
         }
 
         let this = self.project();
-        match ready!(this.task.poll(cx)) {
+        let mut task = this.task;
+        let polled = match std::panic::catch_unwind(AssertUnwindSafe(|| task.as_mut().poll(cx))) {
+            Ok(polled) => polled,
+            Err(payload) => Poll::Ready(Err(FError::from_panic(None, None, payload))),
+        };
+        match ready!(polled) {
             Ok(()) => {
                 debug!("source of job({}) is exhausted;", this.job_id);
                 Poll::Ready(())
             }
             Err(e) => {
                 error!("source of job({}) poll fail: {}", this.job_id, e);
-                this.error_hook.set_error(e);
+                this.error_hook.set_error(None, None, None, e);
                 Poll::Ready(())
             }
         }
     }
 }
 
-pub struct StageInput<T>(Receiver<T>);
+pub struct StageInput<T> {
+    receiver: Receiver<T>,
+    probe: Option<InputProbe>,
+}
 
 impl<T> StageInput<T> {
     pub fn new(receiver: Receiver<T>) -> Self {
-        StageInput(receiver)
+        StageInput { receiver, probe: None }
+    }
+
+    /// Create an input inside a loop, which reports items received to the loop scope;
+    pub fn with_scope(receiver: Receiver<T>, scope: Arc<LoopScope>) -> Self {
+        StageInput { receiver, probe: Some(InputProbe::new(scope)) }
     }
 }
 
@@ -64,6 +88,11 @@ impl<T> Stream for StageInput<T> {
 
     #[inline]
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        Pin::new(&mut self.get_mut().0).poll_next(cx)
+        let this = self.get_mut();
+        let next = Pin::new(&mut this.receiver).poll_next(cx);
+        if let Some(probe) = this.probe.as_mut() {
+            probe.on_next(matches!(next, Poll::Ready(Some(_))));
+        }
+        next
     }
 }
@@ -1,44 +1,195 @@
-use std::cell::UnsafeCell;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
 
-use crate::FError;
+use crate::errors::{FError, JobError};
 
 pub struct ErrorHook {
+    job_id: u64,
     has_error: AtomicBool,
-    error: UnsafeCell<Option<FError>>,
+    // at most `max_errors` errors are kept, the first one aborts the job; they are `None` once taken;
+    max_errors: usize,
+    errors: Mutex<Option<Vec<JobError>>>,
+    is_cancelled: AtomicBool,
+    is_stopped: AtomicBool,
+    running: AtomicUsize,
+    next_task: AtomicUsize,
+    // wakers of running tasks, they are woken to abort once error occurred or job is cancelled;
+    tasks: Mutex<Vec<Option<Waker>>>,
+    // wakers of who are waiting for all tasks finished;
+    waiters: Mutex<Vec<Waker>>,
 }
 
 impl ErrorHook {
-    pub fn new() -> Self {
-        Self { has_error: AtomicBool::new(false), error: UnsafeCell::new(None) }
+    pub fn new(job_id: u64, max_errors: usize) -> Self {
+        Self {
+            job_id,
+            has_error: AtomicBool::new(false),
+            max_errors,
+            errors: Mutex::new(Some(Vec::new())),
+            is_cancelled: AtomicBool::new(false),
+            is_stopped: AtomicBool::new(false),
+            running: AtomicUsize::new(0),
+            next_task: AtomicUsize::new(0),
+            tasks: Mutex::new(Vec::new()),
+            waiters: Mutex::new(Vec::new()),
+        }
     }
 
-    pub fn set_error(&self, error: FError) -> Option<FError> {
-        if self
-            .has_error
-            .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
-            .is_ok()
-        {
-            unsafe { &mut *self.error.get() }.replace(error);
-            None
-        } else {
-            Some(error)
+    /// Record the error occurred in the stage of the worker, or in the source if they are `None`, the first error
+    /// aborts the job, later ones are kept until `max_errors` errors are kept or errors are taken, the others are
+    /// returned;
+    pub fn set_error(
+        &self, worker: Option<u32>, stage: Option<u32>, operator: Option<&'static str>, error: FError,
+    ) -> Option<JobError> {
+        let error = JobError::new(self.job_id, worker, stage, operator, error);
+        let mut errors = self.errors.lock().expect("lock errors fail;");
+        if !self.has_error.swap(true, Ordering::SeqCst) {
+            if let Some(errors) = errors.as_mut() {
+                errors.push(error);
+            }
+            drop(errors);
+            self.wake_tasks();
+            return None;
+        }
+        match errors.as_mut() {
+            // send errors after the first error are caused by aborted receivers, they are not kept;
+            Some(errors) if errors.len() < self.max_errors && !matches!(error.error(), FError::ChSend(_)) => {
+                errors.push(error);
+                None
+            }
+            _ => Some(error),
         }
     }
 
-    pub fn take_error(&self) -> Option<FError> {
-        if self.has_error.load(Ordering::SeqCst) {
-            unsafe { &mut *self.error.get() }.take()
-        } else {
+    /// Take the first error, with the other errors kept attached to it, errors are only taken once;
+    pub fn take_error(&self) -> Option<JobError> {
+        if !self.has_error.load(Ordering::SeqCst) {
+            return None;
+        }
+        let mut errors = self.errors.lock().expect("lock errors fail;").take()?;
+        if errors.is_empty() {
             None
+        } else {
+            let mut first = errors.remove(0);
+            first.suppress(errors);
+            Some(first)
         }
     }
 
+    /// If more errors than the first one are kept, see `JobConfig::collect_errors`;
+    #[inline]
+    pub fn is_collecting(&self) -> bool {
+        self.max_errors > 1
+    }
+
     #[inline]
     pub fn has_error(&self) -> bool {
         self.has_error.load(Ordering::SeqCst)
     }
+
+    /// Cancel the job, all running tasks are woken to abort;
+    pub fn cancel(&self) {
+        if !self.is_cancelled.swap(true, Ordering::SeqCst) {
+            self.wake_tasks();
+        }
+    }
+
+    #[inline]
+    pub fn is_cancelled(&self) -> bool {
+        self.is_cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Stop sources of the job without error, stages go on until their inputs are exhausted;
+    pub fn stop_sources(&self) {
+        if !self.is_stopped.swap(true, Ordering::SeqCst) {
+            self.wake_tasks();
+        }
+    }
+
+    #[inline]
+    pub fn is_stopped(&self) -> bool {
+        self.is_stopped.load(Ordering::SeqCst)
+    }
+
+    /// If tasks of the job should abort, because of error or cancellation;
+    #[inline]
+    pub fn is_aborted(&self) -> bool {
+        self.has_error() || self.is_cancelled()
+    }
+
+    /// Register a task of the job, the task is tracked until the returned guard is dropped;
+    pub fn new_task(self: &Arc<Self>) -> TaskGuard {
+        self.running.fetch_add(1, Ordering::SeqCst);
+        let index = self.next_task.fetch_add(1, Ordering::SeqCst);
+        TaskGuard { index, hook: self.clone() }
+    }
+
+    #[inline]
+    pub fn is_finished(&self) -> bool {
+        self.running.load(Ordering::SeqCst) == 0
+    }
+
+    /// Poll until all tasks of the job are finished;
+    pub fn poll_finished(&self, cx: &mut Context<'_>) -> Poll<()> {
+        if self.is_finished() {
+            return Poll::Ready(());
+        }
+        let mut waiters = self.waiters.lock().expect("lock waiters fail;");
+        // a waiter polled again is registered only once;
+        if !waiters.iter().any(|w| w.will_wake(cx.waker())) {
+            waiters.push(cx.waker().clone());
+        }
+        drop(waiters);
+        if self.is_finished() {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+
+    fn wake_tasks(&self) {
+        let tasks = self.tasks.lock().expect("lock tasks fail;");
+        for waker in tasks.iter().flatten() {
+            waker.wake_by_ref();
+        }
+    }
+
+    fn finish_task(&self, index: usize) {
+        if let Some(waker) = self.tasks.lock().expect("lock tasks fail;").get_mut(index) {
+            waker.take();
+        }
+        if self.running.fetch_sub(1, Ordering::SeqCst) == 1 {
+            let waiters = std::mem::take(&mut *self.waiters.lock().expect("lock waiters fail;"));
+            for waker in waiters {
+                waker.wake();
+            }
+        }
+    }
 }
 
-unsafe impl Send for ErrorHook {}
-unsafe impl Sync for ErrorHook {}
+/// Tracks a running task of a job, the task is finished once the guard is dropped;
+pub struct TaskGuard {
+    index: usize,
+    hook: Arc<ErrorHook>,
+}
+
+impl TaskGuard {
+    /// Register the waker of the task, so it can be woken to abort;
+    pub fn register(&self, waker: &Waker) {
+        let mut tasks = self.hook.tasks.lock().expect("lock tasks fail;");
+        if tasks.len() <= self.index {
+            tasks.resize(self.index + 1, None);
+        }
+        match tasks[self.index].as_ref() {
+            Some(w) if w.will_wake(waker) => (),
+            _ => tasks[self.index] = Some(waker.clone()),
+        }
+    }
+}
+
+impl Drop for TaskGuard {
+    fn drop(&mut self) {
+        self.hook.finish_task(self.index);
+    }
+}
@@ -0,0 +1,119 @@
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures::stream::BoxStream;
+use futures::{ready, Stream};
+use pin_project_lite::pin_project;
+
+use crate::errors::FError;
+use crate::stages::source::StageInput;
+
+pub type Aggregate<A, M> = Merge<Gather<A>, A, M>;
+
+pub type Sum<A> = Aggregate<A, fn(A, A) -> A>;
+
+#[inline]
+pub(crate) fn add<A: std::ops::Add<Output = A>>(a: A, b: A) -> A {
+    a + b
+}
+
+pin_project! {
+    /// Fold all items of the upstream from `init`, and emit the partial result once after the upstream is exhausted,
+    /// the partial result is always emitted even if the upstream is empty;
+    pub struct Partial<St, A, F> {
+        #[pin]
+        stream: St,
+        acc: Option<A>,
+        fold: F,
+    }
+}
+
+impl<St, A, F> Partial<St, A, F> {
+    pub fn new(stream: St, init: A, fold: F) -> Self {
+        Self { stream, acc: Some(init), fold }
+    }
+}
+
+impl<St, T, A, F> Stream for Partial<St, A, F>
+where
+    St: Stream<Item = Result<T, FError>>,
+    F: FnMut(A, T) -> A,
+{
+    type Item = Result<A, FError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        if this.acc.is_none() {
+            return Poll::Ready(None);
+        }
+
+        while let Some(item) = ready!(this.stream.as_mut().poll_next(cx)) {
+            let item = item?;
+            let acc = this.acc.take().expect("partial result lost;");
+            *this.acc = Some((this.fold)(acc, item));
+        }
+        Poll::Ready(this.acc.take().map(Ok))
+    }
+}
+
+/// Partial results to merge, they are gathered from all workers by a channel, or taken from the partial result of the
+/// only worker of the job without a channel;
+pub enum Gather<A> {
+    Channel(StageInput<A>),
+    Local(BoxStream<'static, Result<A, FError>>),
+}
+
+impl<A> Stream for Gather<A> {
+    type Item = Result<A, FError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        match self.get_mut() {
+            Gather::Channel(input) => Pin::new(input).poll_next(cx).map(|part| part.map(Ok)),
+            Gather::Local(partial) => partial.as_mut().poll_next(cx),
+        }
+    }
+}
+
+pin_project! {
+    /// Merge all partial results received, and emit the final result once after the upstream is exhausted,
+    /// nothing is emitted if no partial result is received;
+    pub struct Merge<St, A, M> {
+        #[pin]
+        stream: St,
+        acc: Option<A>,
+        merge: M,
+        is_done: bool,
+    }
+}
+
+impl<St, A, M> Merge<St, A, M> {
+    pub fn new(stream: St, merge: M) -> Self {
+        Self { stream, acc: None, merge, is_done: false }
+    }
+}
+
+impl<St, A, M> Stream for Merge<St, A, M>
+where
+    St: Stream<Item = Result<A, FError>>,
+    M: FnMut(A, A) -> A,
+{
+    type Item = Result<A, FError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        if *this.is_done {
+            return Poll::Ready(None);
+        }
+
+        while let Some(part) = ready!(this.stream.as_mut().poll_next(cx)) {
+            let part = part?;
+            let acc = match this.acc.take() {
+                Some(acc) => (this.merge)(acc, part),
+                None => part,
+            };
+            *this.acc = Some(acc);
+        }
+        *this.is_done = true;
+        Poll::Ready(this.acc.take().map(Ok))
+    }
+}
@@ -0,0 +1,307 @@
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures::{ready, Stream};
+use pin_project_lite::pin_project;
+
+use crate::errors::FError;
+
+// Combinators on ok items of the upstream, error items are always passed through. Combinators which stop early
+// still drain the upstream, otherwise upstream stages fail to send to it once it's dropped.
+
+pin_project! {
+    pub struct Filter<St, P> {
+        #[pin]
+        stream: St,
+        predicate: P,
+    }
+}
+
+impl<St, P> Filter<St, P> {
+    pub fn new(stream: St, predicate: P) -> Self {
+        Self { stream, predicate }
+    }
+}
+
+impl<St, T, P> Stream for Filter<St, P>
+where
+    St: Stream<Item = Result<T, FError>>,
+    P: FnMut(&T) -> bool,
+{
+    type Item = Result<T, FError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        loop {
+            match ready!(this.stream.as_mut().poll_next(cx)) {
+                Some(Ok(item)) if !(this.predicate)(&item) => continue,
+                item => return Poll::Ready(item),
+            }
+        }
+    }
+}
+
+pin_project! {
+    pub struct FilterMap<St, F> {
+        #[pin]
+        stream: St,
+        f: F,
+    }
+}
+
+impl<St, F> FilterMap<St, F> {
+    pub fn new(stream: St, f: F) -> Self {
+        Self { stream, f }
+    }
+}
+
+impl<St, T, U, F> Stream for FilterMap<St, F>
+where
+    St: Stream<Item = Result<T, FError>>,
+    F: FnMut(T) -> Option<U>,
+{
+    type Item = Result<U, FError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        loop {
+            match ready!(this.stream.as_mut().poll_next(cx)) {
+                Some(Ok(item)) => {
+                    if let Some(mapped) = (this.f)(item) {
+                        return Poll::Ready(Some(Ok(mapped)));
+                    }
+                }
+                Some(Err(e)) => return Poll::Ready(Some(Err(e))),
+                None => return Poll::Ready(None),
+            }
+        }
+    }
+}
+
+pin_project! {
+    pub struct TryFilter<St, P> {
+        #[pin]
+        stream: St,
+        predicate: P,
+    }
+}
+
+impl<St, P> TryFilter<St, P> {
+    pub fn new(stream: St, predicate: P) -> Self {
+        Self { stream, predicate }
+    }
+}
+
+impl<St, T, P> Stream for TryFilter<St, P>
+where
+    St: Stream<Item = Result<T, FError>>,
+    P: FnMut(&T) -> Result<bool, FError>,
+{
+    type Item = Result<T, FError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        loop {
+            match ready!(this.stream.as_mut().poll_next(cx)) {
+                Some(Ok(item)) => match (this.predicate)(&item) {
+                    Ok(true) => return Poll::Ready(Some(Ok(item))),
+                    Ok(false) => continue,
+                    Err(e) => return Poll::Ready(Some(Err(e))),
+                },
+                item => return Poll::Ready(item),
+            }
+        }
+    }
+}
+
+pin_project! {
+    pub struct TakeWhile<St, P> {
+        #[pin]
+        stream: St,
+        predicate: P,
+        is_done: bool,
+    }
+}
+
+impl<St, P> TakeWhile<St, P> {
+    pub fn new(stream: St, predicate: P) -> Self {
+        Self { stream, predicate, is_done: false }
+    }
+}
+
+impl<St, T, P> Stream for TakeWhile<St, P>
+where
+    St: Stream<Item = Result<T, FError>>,
+    P: FnMut(&T) -> bool,
+{
+    type Item = Result<T, FError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        loop {
+            match ready!(this.stream.as_mut().poll_next(cx)) {
+                Some(Ok(item)) => {
+                    if !*this.is_done && (this.predicate)(&item) {
+                        return Poll::Ready(Some(Ok(item)));
+                    }
+                    *this.is_done = true;
+                }
+                item => return Poll::Ready(item),
+            }
+        }
+    }
+}
+
+pin_project! {
+    pub struct SkipWhile<St, P> {
+        #[pin]
+        stream: St,
+        predicate: P,
+        is_skipping: bool,
+    }
+}
+
+impl<St, P> SkipWhile<St, P> {
+    pub fn new(stream: St, predicate: P) -> Self {
+        Self { stream, predicate, is_skipping: true }
+    }
+}
+
+impl<St, T, P> Stream for SkipWhile<St, P>
+where
+    St: Stream<Item = Result<T, FError>>,
+    P: FnMut(&T) -> bool,
+{
+    type Item = Result<T, FError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        loop {
+            match ready!(this.stream.as_mut().poll_next(cx)) {
+                Some(Ok(item)) if *this.is_skipping => {
+                    if !(this.predicate)(&item) {
+                        *this.is_skipping = false;
+                        return Poll::Ready(Some(Ok(item)));
+                    }
+                }
+                item => return Poll::Ready(item),
+            }
+        }
+    }
+}
+
+pin_project! {
+    pub struct Scan<St, S, F> {
+        #[pin]
+        stream: St,
+        state: S,
+        f: F,
+        is_done: bool,
+    }
+}
+
+impl<St, S, F> Scan<St, S, F> {
+    pub fn new(stream: St, state: S, f: F) -> Self {
+        Self { stream, state, f, is_done: false }
+    }
+}
+
+impl<St, T, U, S, F> Stream for Scan<St, S, F>
+where
+    St: Stream<Item = Result<T, FError>>,
+    F: FnMut(&mut S, T) -> Option<U>,
+{
+    type Item = Result<U, FError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        loop {
+            match ready!(this.stream.as_mut().poll_next(cx)) {
+                Some(Ok(item)) => {
+                    if !*this.is_done {
+                        match (this.f)(this.state, item) {
+                            Some(output) => return Poll::Ready(Some(Ok(output))),
+                            None => *this.is_done = true,
+                        }
+                    }
+                }
+                Some(Err(e)) => return Poll::Ready(Some(Err(e))),
+                None => return Poll::Ready(None),
+            }
+        }
+    }
+}
+
+pin_project! {
+    pub struct Chunks<St, T> {
+        #[pin]
+        stream: St,
+        buffer: Vec<T>,
+        size: usize,
+    }
+}
+
+impl<St, T> Chunks<St, T> {
+    pub fn new(stream: St, size: usize) -> Self {
+        Self { stream, buffer: Vec::with_capacity(size), size }
+    }
+}
+
+impl<St, T> Stream for Chunks<St, T>
+where
+    St: Stream<Item = Result<T, FError>>,
+{
+    type Item = Result<Vec<T>, FError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        loop {
+            match ready!(this.stream.as_mut().poll_next(cx)) {
+                Some(Ok(item)) => {
+                    this.buffer.push(item);
+                    if this.buffer.len() >= *this.size {
+                        let chunk = std::mem::replace(this.buffer, Vec::with_capacity(*this.size));
+                        return Poll::Ready(Some(Ok(chunk)));
+                    }
+                }
+                Some(Err(e)) => return Poll::Ready(Some(Err(e))),
+                None if this.buffer.is_empty() => return Poll::Ready(None),
+                None => return Poll::Ready(Some(Ok(std::mem::take(this.buffer)))),
+            }
+        }
+    }
+}
+
+pin_project! {
+    pub struct Enumerate<St> {
+        #[pin]
+        stream: St,
+        count: u64,
+    }
+}
+
+impl<St> Enumerate<St> {
+    pub fn new(stream: St) -> Self {
+        Self { stream, count: 0 }
+    }
+}
+
+impl<St, T> Stream for Enumerate<St>
+where
+    St: Stream<Item = Result<T, FError>>,
+{
+    type Item = Result<(u64, T), FError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        match ready!(this.stream.poll_next(cx)) {
+            Some(Ok(item)) => {
+                let index = *this.count;
+                *this.count += 1;
+                Poll::Ready(Some(Ok((index, item))))
+            }
+            Some(Err(e)) => Poll::Ready(Some(Err(e))),
+            None => Poll::Ready(None),
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use std::collections::{HashSet, VecDeque};
+use std::hash::Hash;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use futures::{ready, Stream};
+
+use crate::errors::FError;
+use crate::stages::source::StageInput;
+
+/// Bound of keys remembered by `Distinct`;
+struct Bound<K> {
+    capacity: usize,
+    ttl: Option<Duration>,
+    // keys in the order they are first seen;
+    order: VecDeque<(K, Instant)>,
+}
+
+/// Emit the first item of each key, the other items are dropped; if bounded, keys are forgotten once the capacity is
+/// exceeded or they expire, so the same key may be emitted again;
+pub struct Distinct<K, T> {
+    input: StageInput<(K, T)>,
+    seen: HashSet<K>,
+    bound: Option<Bound<K>>,
+}
+
+impl<K, T> Distinct<K, T> {
+    pub fn new(input: StageInput<(K, T)>) -> Self {
+        Self { input, seen: HashSet::new(), bound: None }
+    }
+
+    pub fn bounded(input: StageInput<(K, T)>, capacity: usize, ttl: Option<Duration>) -> Self {
+        let bound = Bound { capacity, ttl, order: VecDeque::new() };
+        Self { input, seen: HashSet::new(), bound: Some(bound) }
+    }
+}
+
+impl<K, T> Unpin for Distinct<K, T> {}
+
+impl<K, T> Stream for Distinct<K, T>
+where
+    K: Hash + Eq + Clone,
+{
+    type Item = Result<T, FError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        while let Some((key, item)) = ready!(Pin::new(&mut this.input).poll_next(cx)) {
+            let bound = match this.bound.as_mut() {
+                Some(bound) => bound,
+                None => {
+                    if this.seen.insert(key) {
+                        return Poll::Ready(Some(Ok(item)));
+                    }
+                    continue;
+                }
+            };
+
+            let now = Instant::now();
+            if let Some(ttl) = bound.ttl {
+                while let Some((oldest, _)) = bound
+                    .order
+                    .front()
+                    .filter(|(_, at)| now.duration_since(*at) > ttl)
+                {
+                    this.seen.remove(oldest);
+                    bound.order.pop_front();
+                }
+            }
+
+            if !this.seen.contains(&key) {
+                if bound.order.len() >= bound.capacity {
+                    if let Some((oldest, _)) = bound.order.pop_front() {
+                        this.seen.remove(&oldest);
+                    }
+                }
+                this.seen.insert(key.clone());
+                bound.order.push_back((key, now));
+                return Poll::Ready(Some(Ok(item)));
+            }
+        }
+        Poll::Ready(None)
+    }
+}
@@ -0,0 +1,327 @@
+use std::collections::VecDeque;
+use std::hash::Hash;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use futures::future::BoxFuture;
+use futures::stream::Map;
+use futures::{ready, Sink, Stream};
+use pin_project_lite::pin_project;
+use sandflow_executor::Spawner;
+
+use crate::errors::FError;
+use crate::stages::sink::LocalStageSink;
+use crate::stages::source::StageInput;
+use crate::streams::window::{Window, WindowResult, WindowState};
+
+/// An item with its event time in milliseconds, or a watermark which tells no item earlier than it will follow;
+#[derive(Clone, Debug)]
+pub enum Timed<T> {
+    Item(u64, T),
+    Watermark(u64),
+}
+
+impl<T> Timed<T> {
+    pub fn map<U, F: FnOnce(T) -> U>(self, f: F) -> Timed<U> {
+        match self {
+            Timed::Item(ts, item) => Timed::Item(ts, f(item)),
+            Timed::Watermark(watermark) => Timed::Watermark(watermark),
+        }
+    }
+}
+
+pub type KeyedEventWindow<K, T> = EventWindowed<MergeWatermarks<(K, T)>, K, T>;
+
+pub type LocalEventWindow<St, T> =
+    EventWindowed<Map<St, fn(Result<Timed<T>, FError>) -> Result<Timed<((), T)>, FError>>, (), T>;
+
+pin_project! {
+    /// Attach event time to items, and emit a watermark `max_out_of_orderness` behind the latest event time once it
+    /// advances, the final watermark `u64::MAX` is emitted after the upstream is exhausted;
+    pub struct AssignTimestamps<St, F> {
+        #[pin]
+        stream: St,
+        ts_fn: F,
+        delay: u64,
+        max_ts: u64,
+        watermark: u64,
+        pending: Option<u64>,
+        is_done: bool,
+    }
+}
+
+impl<St, F> AssignTimestamps<St, F> {
+    pub fn new(stream: St, ts_fn: F, max_out_of_orderness: u64) -> Self {
+        Self { stream, ts_fn, delay: max_out_of_orderness, max_ts: 0, watermark: 0, pending: None, is_done: false }
+    }
+}
+
+impl<St, T, F> Stream for AssignTimestamps<St, F>
+where
+    St: Stream<Item = Result<T, FError>>,
+    F: FnMut(&T) -> u64,
+{
+    type Item = Result<Timed<T>, FError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        if let Some(watermark) = this.pending.take() {
+            return Poll::Ready(Some(Ok(Timed::Watermark(watermark))));
+        }
+        if *this.is_done {
+            return Poll::Ready(None);
+        }
+
+        match ready!(this.stream.as_mut().poll_next(cx)) {
+            Some(Ok(item)) => {
+                let ts = (this.ts_fn)(&item);
+                if ts > *this.max_ts {
+                    *this.max_ts = ts;
+                    let watermark = ts.saturating_sub(*this.delay);
+                    if watermark > *this.watermark {
+                        *this.watermark = watermark;
+                        *this.pending = Some(watermark);
+                    }
+                }
+                Poll::Ready(Some(Ok(Timed::Item(ts, item))))
+            }
+            Some(Err(e)) => Poll::Ready(Some(Err(e))),
+            None => {
+                *this.is_done = true;
+                Poll::Ready(Some(Ok(Timed::Watermark(u64::MAX))))
+            }
+        }
+    }
+}
+
+pin_project! {
+    /// Map items by `f`, their event times and watermarks are kept;
+    pub struct MapTimed<St, F> {
+        #[pin]
+        stream: St,
+        f: F,
+    }
+}
+
+impl<St, F> MapTimed<St, F> {
+    pub fn new(stream: St, f: F) -> Self {
+        Self { stream, f }
+    }
+}
+
+impl<St, T, U, F> Stream for MapTimed<St, F>
+where
+    St: Stream<Item = Result<Timed<T>, FError>>,
+    F: FnMut(T) -> U,
+{
+    type Item = Result<Timed<U>, FError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        let f = this.f;
+        this.stream
+            .poll_next(cx)
+            .map(|item| item.map(|item| item.map(|timed| timed.map(f))))
+    }
+}
+
+/// Receive items exchanged with their event time, the watermark emitted is the minimum of watermarks of all senders;
+///
+/// With an idle timeout, senders which send nothing for the timeout are ignored until they send again, a timer of the
+/// spawner wakes the stream once a sender becomes idle, so the watermark advances without new items;
+pub struct MergeWatermarks<T> {
+    input: StageInput<(u32, Timed<T>)>,
+    watermarks: Vec<u64>,
+    last_seen: Vec<Instant>,
+    idle: Option<(Duration, Arc<dyn Spawner>)>,
+    timer: Option<(Instant, BoxFuture<'static, ()>)>,
+    current: u64,
+}
+
+impl<T> MergeWatermarks<T> {
+    pub fn new(input: StageInput<(u32, Timed<T>)>, senders: usize, idle: Option<(Duration, Arc<dyn Spawner>)>) -> Self {
+        let last_seen = vec![Instant::now(); senders];
+        Self { input, watermarks: vec![0; senders], last_seen, idle, timer: None, current: 0 }
+    }
+
+    fn is_idle(&self, sender: usize, now: Instant) -> bool {
+        match self.idle.as_ref() {
+            Some((timeout, _)) => now.duration_since(self.last_seen[sender]) >= *timeout,
+            None => false,
+        }
+    }
+
+    /// Advance the watermark to the minimum of senders which are not idle, or the minimum of all if all are idle;
+    fn advance(&mut self, now: Instant) -> Option<u64> {
+        let active = (0..self.watermarks.len())
+            .filter(|i| !self.is_idle(*i, now))
+            .map(|i| self.watermarks[i])
+            .min();
+        let watermark = active.or_else(|| self.watermarks.iter().copied().min())?;
+        if watermark > self.current {
+            self.current = watermark;
+            Some(watermark)
+        } else {
+            None
+        }
+    }
+
+    /// The earliest time a sender holding back the watermark becomes idle;
+    fn next_idle(&self, now: Instant) -> Option<Instant> {
+        let (timeout, _) = self.idle.as_ref()?;
+        if self.current == u64::MAX {
+            return None;
+        }
+        (0..self.watermarks.len())
+            .filter(|i| self.watermarks[*i] <= self.current && !self.is_idle(*i, now))
+            .map(|i| self.last_seen[i] + *timeout)
+            .min()
+    }
+}
+
+impl<T> Unpin for MergeWatermarks<T> {}
+
+impl<T> Stream for MergeWatermarks<T> {
+    type Item = Result<Timed<T>, FError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            match Pin::new(&mut this.input).poll_next(cx) {
+                Poll::Ready(Some((sender, timed))) => {
+                    let now = Instant::now();
+                    this.last_seen[sender as usize] = now;
+                    match timed {
+                        Timed::Item(ts, item) => return Poll::Ready(Some(Ok(Timed::Item(ts, item)))),
+                        Timed::Watermark(watermark) => {
+                            let sender_watermark = &mut this.watermarks[sender as usize];
+                            *sender_watermark = (*sender_watermark).max(watermark);
+                            if let Some(watermark) = this.advance(now) {
+                                return Poll::Ready(Some(Ok(Timed::Watermark(watermark))));
+                            }
+                        }
+                    }
+                    continue;
+                }
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => (),
+            }
+
+            let now = Instant::now();
+            if let Some(watermark) = this.advance(now) {
+                return Poll::Ready(Some(Ok(Timed::Watermark(watermark))));
+            }
+            let deadline = match this.next_idle(now) {
+                Some(deadline) => deadline,
+                None => return Poll::Pending,
+            };
+            if this.timer.as_ref().map_or(true, |(at, _)| *at != deadline) {
+                let (_, spawner) = this.idle.as_ref().expect("idle timer without spawner;");
+                this.timer = Some((deadline, spawner.sleep(deadline.saturating_duration_since(now))));
+            }
+            let (_, timer) = this.timer.as_mut().expect("idle timer lost;");
+            match timer.as_mut().poll(cx) {
+                Poll::Ready(()) => this.timer = None,
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+pin_project! {
+    /// Group items into windows by their event time, windows are fired once the watermark passes their ends, and
+    /// items whose windows are all fired are late, they are sent to the late sink;
+    pub struct EventWindowed<St, K, T> {
+        #[pin]
+        stream: St,
+        state: WindowState<K, T>,
+        watermark: u64,
+        output: VecDeque<WindowResult<K, T>>,
+        late: Option<LocalStageSink<T>>,
+        pending_late: Option<T>,
+        is_done: bool,
+    }
+}
+
+impl<St, K, T> EventWindowed<St, K, T>
+where
+    K: Hash + Eq + Clone,
+    T: Clone,
+{
+    pub fn new(stream: St, window: Window, late: LocalStageSink<T>) -> Self {
+        let state = WindowState::new(window);
+        Self { stream, state, watermark: 0, output: VecDeque::new(), late: Some(late), pending_late: None, is_done: false }
+    }
+}
+
+impl<St, K, T> Stream for EventWindowed<St, K, T>
+where
+    St: Stream<Item = Result<Timed<(K, T)>, FError>>,
+    K: Hash + Eq + Clone,
+    T: Clone,
+{
+    type Item = Result<WindowResult<K, T>, FError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        loop {
+            if let Some(item) = this.pending_late.take() {
+                if let Some(late) = this.late.as_mut() {
+                    match Pin::new(&mut *late).poll_ready(cx) {
+                        Poll::Ready(Ok(())) => Pin::new(&mut *late).start_send(item)?,
+                        Poll::Ready(Err(_)) => {
+                            warn!("drop late items as the late stream is dropped;");
+                            *this.late = None;
+                        }
+                        Poll::Pending => {
+                            *this.pending_late = Some(item);
+                            return Poll::Pending;
+                        }
+                    }
+                }
+            }
+
+            if let Some(result) = this.output.pop_front() {
+                return Poll::Ready(Some(Ok(result)));
+            }
+
+            if *this.is_done {
+                if let Some(late) = this.late.as_mut() {
+                    // errors are ignored as the late stream may be dropped;
+                    let _ = ready!(Pin::new(late).poll_close(cx));
+                    *this.late = None;
+                }
+                return Poll::Ready(None);
+            }
+
+            match this.stream.as_mut().poll_next(cx) {
+                Poll::Ready(Some(Ok(Timed::Item(ts, (key, item))))) => {
+                    if this.state.is_late(ts, *this.watermark) {
+                        *this.pending_late = Some(item);
+                    } else {
+                        this.state.assign(key, ts, item);
+                    }
+                }
+                Poll::Ready(Some(Ok(Timed::Watermark(watermark)))) => {
+                    if watermark > *this.watermark {
+                        *this.watermark = watermark;
+                        this.state.fire(watermark, this.output);
+                    }
+                }
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(e))),
+                Poll::Ready(None) => {
+                    *this.is_done = true;
+                    this.state.flush(this.output);
+                }
+                Poll::Pending => {
+                    if let Some(late) = this.late.as_mut() {
+                        let _ = ready!(Pin::new(late).poll_flush(cx));
+                    }
+                    return Poll::Pending;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use futures::{ready, Sink, Stream};
+use pin_project_lite::pin_project;
+
+use crate::errors::FError;
+use crate::stages::scope::{LoopScope, FINISHED_ROUND};
+use crate::stages::sink::LocalStageSink;
+use crate::stages::source::StageInput;
+
+pin_project! {
+    /// The head of a loop, it emits items into the loop body round by round: items of the upstream in the first round,
+    /// and items fed back during a round are emitted in the next round after all local peers finish the round;
+    pub struct LoopHead<St, T> {
+        #[pin]
+        input: St,
+        feedback: StageInput<T>,
+        sink: Option<LocalStageSink<T>>,
+        scope: Arc<LoopScope>,
+        worker_index: usize,
+        round: u32,
+        current: VecDeque<T>,
+        next: VecDeque<T>,
+        // items fed back during the round after `round`, they arrive if peers start that round before this head;
+        later: VecDeque<T>,
+        is_emitting: bool,
+        is_input_done: bool,
+    }
+}
+
+impl<St, T> LoopHead<St, T> {
+    pub fn new(
+        worker_index: usize, scope: Arc<LoopScope>, input: St, feedback: StageInput<T>, sink: LocalStageSink<T>,
+    ) -> Self {
+        Self {
+            input,
+            feedback,
+            sink: Some(sink),
+            scope,
+            worker_index,
+            round: 0,
+            current: VecDeque::new(),
+            next: VecDeque::new(),
+            later: VecDeque::new(),
+            is_emitting: true,
+            is_input_done: false,
+        }
+    }
+}
+
+impl<St, T> Future for LoopHead<St, T>
+where
+    St: Stream<Item = Result<T, FError>>,
+{
+    type Output = Result<(), FError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut this = self.project();
+        loop {
+            // items fed back are always taken in time, so the loop body never blocks on feedback; the round can't
+            // advance while the feedback is being drained, as its input is busy until then;
+            while let Poll::Ready(Some(item)) = Pin::new(&mut *this.feedback).poll_next(cx) {
+                if this.scope.round() == *this.round {
+                    this.next.push_back(item);
+                } else {
+                    this.later.push_back(item);
+                }
+                this.scope.add_next_round_items(1);
+            }
+
+            if *this.is_emitting {
+                if this.current.is_empty() && *this.round == 0 && !*this.is_input_done {
+                    match ready!(this.input.as_mut().poll_next(cx)) {
+                        Some(item) => this.current.push_back(item?),
+                        None => *this.is_input_done = true,
+                    }
+                }
+
+                match this.current.pop_front() {
+                    Some(item) => {
+                        let sink = this.sink.as_mut().expect("loop head is closed;");
+                        if Pin::new(&mut *sink).poll_ready(cx)?.is_pending() {
+                            this.current.push_front(item);
+                            return Poll::Pending;
+                        }
+                        Pin::new(&mut *sink).start_send(item)?;
+                    }
+                    None => {
+                        *this.is_emitting = false;
+                        this.scope.finish_emit();
+                    }
+                }
+                continue;
+            }
+
+            this.scope.register(*this.worker_index, cx.waker());
+            if this.scope.round() == *this.round && !this.scope.try_advance(*this.round) {
+                return Poll::Pending;
+            }
+
+            let round = this.scope.round();
+            if round == FINISHED_ROUND {
+                if let Some(sink) = this.sink.as_mut() {
+                    ready!(Pin::new(sink).poll_close(cx))?;
+                    *this.sink = None;
+                }
+                return Poll::Ready(Ok(()));
+            }
+
+            trace!("worker[{}]: start round {} of iteration;", this.worker_index, round);
+            *this.round = round;
+            *this.current = std::mem::replace(this.next, std::mem::take(this.later));
+            this.scope.take_next_round_items(this.current.len());
+            *this.is_emitting = true;
+        }
+    }
+}
@@ -0,0 +1,154 @@
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures::{ready, Stream};
+
+use crate::errors::FError;
+use crate::stages::source::StageInput;
+
+/// How a left item is joined with right items of the same key;
+pub trait JoinMode<L, R> {
+    type Output;
+
+    fn matched(left: L, right: R) -> Self::Output;
+
+    /// Called if no right item has the key of the left item, return `None` to drop it;
+    fn unmatched(left: L) -> Option<Self::Output>;
+}
+
+/// Emit `(left, right)` for each matched pair;
+pub struct Inner;
+
+impl<L, R> JoinMode<L, R> for Inner {
+    type Output = (L, R);
+
+    #[inline]
+    fn matched(left: L, right: R) -> Self::Output {
+        (left, right)
+    }
+
+    #[inline]
+    fn unmatched(_: L) -> Option<Self::Output> {
+        None
+    }
+}
+
+/// Emit `(left, Some(right))` for each matched pair, and `(left, None)` for each left item without match;
+pub struct LeftOuter;
+
+impl<L, R> JoinMode<L, R> for LeftOuter {
+    type Output = (L, Option<R>);
+
+    #[inline]
+    fn matched(left: L, right: R) -> Self::Output {
+        (left, Some(right))
+    }
+
+    #[inline]
+    fn unmatched(left: L) -> Option<Self::Output> {
+        Some((left, None))
+    }
+}
+
+/// Join items of the left input with items of the right input by key, all items of the right input are
+/// collected into a hash table before any item of the left input is joined; left items arriving earlier are
+/// buffered, so both inputs can be fed by the same upstream, e.g. a self-join of `split` branches;
+pub struct HashJoin<K, L, R, M: JoinMode<L, R>> {
+    left: StageInput<(K, L)>,
+    right: StageInput<(K, R)>,
+    table: HashMap<K, Vec<R>>,
+    is_built: bool,
+    buffered: VecDeque<(K, L)>,
+    is_left_done: bool,
+    pending: VecDeque<M::Output>,
+    _ph: PhantomData<M>,
+}
+
+impl<K, L, R, M: JoinMode<L, R>> HashJoin<K, L, R, M> {
+    pub fn new(left: StageInput<(K, L)>, right: StageInput<(K, R)>) -> Self {
+        Self {
+            left,
+            right,
+            table: HashMap::new(),
+            is_built: false,
+            buffered: VecDeque::new(),
+            is_left_done: false,
+            pending: VecDeque::new(),
+            _ph: PhantomData,
+        }
+    }
+
+    /// Buffer left items which are ready without waiting, so the upstream of the left input is never blocked;
+    fn buffer_left(&mut self, cx: &mut Context<'_>) {
+        while !self.is_left_done {
+            match Pin::new(&mut self.left).poll_next(cx) {
+                Poll::Ready(Some(item)) => self.buffered.push_back(item),
+                Poll::Ready(None) => self.is_left_done = true,
+                Poll::Pending => break,
+            }
+        }
+    }
+
+    fn poll_left(&mut self, cx: &mut Context<'_>) -> Poll<Option<(K, L)>> {
+        if let Some(item) = self.buffered.pop_front() {
+            return Poll::Ready(Some(item));
+        }
+        if self.is_left_done {
+            return Poll::Ready(None);
+        }
+        Pin::new(&mut self.left).poll_next(cx)
+    }
+}
+
+impl<K, L, R, M: JoinMode<L, R>> Unpin for HashJoin<K, L, R, M> {}
+
+impl<K, L, R, M> Stream for HashJoin<K, L, R, M>
+where
+    K: Hash + Eq,
+    L: Clone,
+    R: Clone,
+    M: JoinMode<L, R>,
+{
+    type Item = Result<M::Output, FError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        while !this.is_built {
+            match Pin::new(&mut this.right).poll_next(cx) {
+                Poll::Ready(Some((key, right))) => this.table.entry(key).or_default().push(right),
+                Poll::Ready(None) => this.is_built = true,
+                Poll::Pending => {
+                    this.buffer_left(cx);
+                    return Poll::Pending;
+                }
+            }
+        }
+
+        loop {
+            if let Some(joined) = this.pending.pop_front() {
+                return Poll::Ready(Some(Ok(joined)));
+            }
+
+            match ready!(this.poll_left(cx)) {
+                Some((key, left)) => match this.table.get(&key) {
+                    Some(rights) => {
+                        let (last, others) = rights.split_last().expect("empty join bucket;");
+                        for right in others {
+                            this.pending.push_back(M::matched(left.clone(), right.clone()));
+                        }
+                        this.pending.push_back(M::matched(left, last.clone()));
+                    }
+                    None => {
+                        if let Some(joined) = M::unmatched(left) {
+                            return Poll::Ready(Some(Ok(joined)));
+                        }
+                    }
+                },
+                None => return Poll::Ready(None),
+            }
+        }
+    }
+}
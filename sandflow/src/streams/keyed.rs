@@ -0,0 +1,182 @@
+use std::collections::hash_map::{DefaultHasher, IntoIter};
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures::{ready, Stream};
+use pin_project_lite::pin_project;
+
+use crate::errors::FError;
+use crate::stages::source::StageInput;
+
+pub type FoldByKey<K, T, V, I, F> = GroupByKey<StageInput<(K, T)>, K, V, Fold<I, F>>;
+
+pub type ReduceByKey<K, T, F> = GroupByKey<StageInput<(K, T)>, K, T, Reduce<F>>;
+
+/// Hash a key to route it between workers, the result is stable among all local peers;
+#[inline]
+pub fn hash_key<K: Hash + ?Sized>(key: &K) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// How items of the same key are folded into an accumulator;
+pub trait KeyedFold<T, V> {
+    /// Create the accumulator with the first item of a key;
+    fn first(&mut self, item: T) -> V;
+
+    /// Fold the next item of a key into its accumulator;
+    fn next(&mut self, acc: V, item: T) -> V;
+}
+
+/// How accumulators of the same key are merged, if items of the key are folded into separate accumulators;
+pub trait KeyedMerge<V> {
+    fn merge(&mut self, acc: V, other: V) -> V;
+}
+
+/// Fold items of a key from the initial value created by `init`;
+pub struct Fold<I, F> {
+    init: I,
+    func: F,
+}
+
+impl<I, F> Fold<I, F> {
+    pub fn new(init: I, func: F) -> Self {
+        Self { init, func }
+    }
+}
+
+impl<T, V, I, F> KeyedFold<T, V> for Fold<I, F>
+where
+    I: FnMut() -> V,
+    F: FnMut(V, T) -> V,
+{
+    #[inline]
+    fn first(&mut self, item: T) -> V {
+        (self.func)((self.init)(), item)
+    }
+
+    #[inline]
+    fn next(&mut self, acc: V, item: T) -> V {
+        (self.func)(acc, item)
+    }
+}
+
+pub struct Reduce<F>(F);
+
+impl<F> Reduce<F> {
+    pub fn new(func: F) -> Self {
+        Reduce(func)
+    }
+}
+
+impl<T, F> KeyedFold<T, T> for Reduce<F>
+where
+    F: FnMut(T, T) -> T,
+{
+    #[inline]
+    fn first(&mut self, item: T) -> T {
+        item
+    }
+
+    #[inline]
+    fn next(&mut self, acc: T, item: T) -> T {
+        (self.0)(acc, item)
+    }
+}
+
+impl<T, F> KeyedMerge<T> for Reduce<F>
+where
+    F: FnMut(T, T) -> T,
+{
+    #[inline]
+    fn merge(&mut self, acc: T, other: T) -> T {
+        (self.0)(acc, other)
+    }
+}
+
+/// Fold with a function to merge accumulators;
+pub struct FoldMerge<I, F, M> {
+    fold: Fold<I, F>,
+    merge: M,
+}
+
+impl<I, F, M> FoldMerge<I, F, M> {
+    pub fn new(fold: Fold<I, F>, merge: M) -> Self {
+        Self { fold, merge }
+    }
+}
+
+impl<T, V, I, F, M> KeyedFold<T, V> for FoldMerge<I, F, M>
+where
+    I: FnMut() -> V,
+    F: FnMut(V, T) -> V,
+{
+    #[inline]
+    fn first(&mut self, item: T) -> V {
+        self.fold.first(item)
+    }
+
+    #[inline]
+    fn next(&mut self, acc: V, item: T) -> V {
+        self.fold.next(acc, item)
+    }
+}
+
+impl<V, I, F, M> KeyedMerge<V> for FoldMerge<I, F, M>
+where
+    M: FnMut(V, V) -> V,
+{
+    #[inline]
+    fn merge(&mut self, acc: V, other: V) -> V {
+        (self.merge)(acc, other)
+    }
+}
+
+pin_project! {
+    /// Fold all `(key, item)` pairs of the upstream by key, and emit one `(key, value)` for each key
+    /// after the upstream is exhausted;
+    pub struct GroupByKey<St, K, V, A> {
+        #[pin]
+        stream: St,
+        agg: A,
+        // an accumulator is taken out of its entry while it's folded;
+        table: HashMap<K, Option<V>>,
+        output: Option<IntoIter<K, Option<V>>>,
+    }
+}
+
+impl<St, K, V, A> GroupByKey<St, K, V, A> {
+    pub fn new(stream: St, agg: A) -> Self {
+        Self { stream, agg, table: HashMap::new(), output: None }
+    }
+}
+
+impl<St, K, T, V, A> Stream for GroupByKey<St, K, V, A>
+where
+    St: Stream<Item = (K, T)>,
+    K: Hash + Eq,
+    A: KeyedFold<T, V>,
+{
+    type Item = Result<(K, V), FError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        if this.output.is_none() {
+            while let Some((key, item)) = ready!(this.stream.as_mut().poll_next(cx)) {
+                let slot = this.table.entry(key).or_insert(None);
+                let acc = match slot.take() {
+                    Some(acc) => this.agg.next(acc, item),
+                    None => this.agg.first(item),
+                };
+                *slot = Some(acc);
+            }
+            *this.output = Some(std::mem::take(this.table).into_iter());
+        }
+
+        let next = this.output.as_mut().and_then(|iter| iter.next());
+        Poll::Ready(next.map(|(key, acc)| Ok((key, acc.expect("accumulator lost;")))))
+    }
+}
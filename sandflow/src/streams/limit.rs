@@ -0,0 +1,58 @@
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use futures::{ready, Stream};
+use pin_project_lite::pin_project;
+
+use crate::errors::FError;
+use crate::stages::utils::ErrorHook;
+
+pin_project! {
+    /// Emit ok items until `remaining` shared by all workers is used up, then sources of the job are stopped, and
+    /// items left in the upstream are drained and dropped;
+    pub struct Limit<St> {
+        #[pin]
+        stream: St,
+        remaining: Arc<AtomicU64>,
+        error_hook: Arc<ErrorHook>,
+    }
+}
+
+impl<St> Limit<St> {
+    pub fn new(stream: St, remaining: Arc<AtomicU64>, error_hook: Arc<ErrorHook>) -> Self {
+        if remaining.load(Ordering::SeqCst) == 0 {
+            error_hook.stop_sources();
+        }
+        Self { stream, remaining, error_hook }
+    }
+}
+
+impl<St, T> Stream for Limit<St>
+where
+    St: Stream<Item = Result<T, FError>>,
+{
+    type Item = Result<T, FError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        loop {
+            match ready!(this.stream.as_mut().poll_next(cx)) {
+                Some(Ok(item)) => {
+                    let taken = this
+                        .remaining
+                        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1));
+                    if let Ok(n) = taken {
+                        if n == 1 {
+                            debug!("limit is reached, stop sources;");
+                            this.error_hook.stop_sources();
+                        }
+                        return Poll::Ready(Some(Ok(item)));
+                    }
+                }
+                item => return Poll::Ready(item),
+            }
+        }
+    }
+}
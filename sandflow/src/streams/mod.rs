@@ -17,6 +17,21 @@ pub trait StreamExtend: Stream {
 
 impl<T: ?Sized> StreamExtend for T where T: Stream {}
 
+pub mod aggregate;
+pub mod combinators;
+pub mod distinct;
+pub mod event_time;
+pub mod iteration;
+pub mod join;
+pub mod keyed;
+pub mod limit;
+pub mod on_error;
 pub mod pstream;
 pub mod result_stream;
+pub mod retry;
 pub mod select_forward;
+pub mod sort;
+pub mod spill;
+pub mod state;
+pub mod top_k;
+pub mod window;
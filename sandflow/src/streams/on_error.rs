@@ -0,0 +1,106 @@
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures::channel::mpsc::{Receiver, Sender};
+use futures::{ready, Sink, Stream};
+use pin_project_lite::pin_project;
+
+use crate::errors::{FError, JobError};
+
+/// Create a dead letter sink and the stream of errors sent to it, the stream ends after all copies of the sink
+/// are dropped; the sink blocks once `capacity` errors are not consumed;
+///
+/// It's created before the job, as the sink is given to policies of all workers, and unlike `ResultStream` the job is
+/// not cancelled if it's dropped, errors sent then are logged and dropped;
+pub fn dead_letters(capacity: usize) -> (DeadLetterSink, DeadLetters) {
+    let (tx, rx) = futures::channel::mpsc::channel(capacity);
+    (DeadLetterSink { sender: tx }, DeadLetters { receiver: rx })
+}
+
+/// What to do with an error item of a stream;
+#[derive(Clone)]
+pub enum ErrorPolicy {
+    /// Abort the job, which is the default behavior;
+    Fail,
+    /// Log and drop the error;
+    Skip,
+    /// Send the error to the dead letter stream and go on;
+    DeadLetter(DeadLetterSink),
+}
+
+#[derive(Clone)]
+pub struct DeadLetterSink {
+    sender: Sender<JobError>,
+}
+
+/// Errors dropped by the `DeadLetter` policy, with the worker where they occurred;
+pub struct DeadLetters {
+    receiver: Receiver<JobError>,
+}
+
+impl Stream for DeadLetters {
+    type Item = JobError;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.get_mut().receiver).poll_next(cx)
+    }
+}
+
+pin_project! {
+    /// Handle error items of the upstream by the policy, ok items are passed through;
+    pub struct OnError<St> {
+        #[pin]
+        stream: St,
+        policy: ErrorPolicy,
+        job_id: u64,
+        worker_index: u32,
+        pending: Option<JobError>,
+    }
+}
+
+impl<St> OnError<St> {
+    pub fn new(job_id: u64, worker_index: u32, stream: St, policy: ErrorPolicy) -> Self {
+        Self { stream, policy, job_id, worker_index, pending: None }
+    }
+}
+
+impl<St, T> Stream for OnError<St>
+where
+    St: Stream<Item = Result<T, FError>>,
+{
+    type Item = Result<T, FError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        loop {
+            if let Some(error) = this.pending.take() {
+                if let ErrorPolicy::DeadLetter(sink) = this.policy {
+                    match sink.sender.poll_ready(cx) {
+                        Poll::Ready(Ok(())) => {
+                            let _ = Pin::new(&mut sink.sender).start_send(error);
+                        }
+                        Poll::Ready(Err(_)) => {
+                            warn!("worker[{}]: drop error as dead letters are dropped: {};", this.worker_index, error);
+                        }
+                        Poll::Pending => {
+                            *this.pending = Some(error);
+                            return Poll::Pending;
+                        }
+                    }
+                }
+            }
+
+            match ready!(this.stream.as_mut().poll_next(cx)) {
+                Some(Err(e)) => match this.policy {
+                    ErrorPolicy::Fail => return Poll::Ready(Some(Err(e))),
+                    ErrorPolicy::Skip => warn!("worker[{}]: skip error: {};", this.worker_index, e),
+                    ErrorPolicy::DeadLetter(_) => {
+                        let error = JobError::new(*this.job_id, Some(*this.worker_index), None, Some("on_error"), e);
+                        *this.pending = Some(error);
+                    }
+                },
+                item => return Poll::Ready(item),
+            }
+        }
+    }
+}
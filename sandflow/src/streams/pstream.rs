@@ -1,13 +1,37 @@
 use std::future::Future;
+use std::hash::Hash;
+use std::ops::Add;
+use std::sync::atomic::AtomicU64;
+use std::time::Duration;
 
-use futures::stream::{FlatMap, Forward, Inspect, Map, Then};
+use futures::stream::{BufferUnordered, Buffered, FlatMap, Forward, Inspect, Map, Then};
 use futures::{Sink, Stream, StreamExt, TryStream};
 
 use super::StreamExtend;
 use crate::errors::FError;
 use crate::flow::SandFlowBuilder;
+use crate::stages::scope::LoopScope;
+use crate::stages::sink::broadcast::BroadcastSink;
 use crate::stages::sink::select::SelectSink;
+use crate::stages::sink::watermark::WatermarkSink;
 use crate::stages::source::StageInput;
+use crate::streams::aggregate::{add, Aggregate, Gather, Merge, Partial, Sum};
+use crate::streams::combinators::{Chunks, Enumerate, Filter, FilterMap, Scan, SkipWhile, TakeWhile, TryFilter};
+use crate::streams::distinct::Distinct;
+use crate::streams::event_time::{
+    AssignTimestamps, EventWindowed, KeyedEventWindow, LocalEventWindow, MapTimed, MergeWatermarks, Timed,
+};
+use crate::streams::iteration::LoopHead;
+use crate::streams::join::{HashJoin, Inner, JoinMode, LeftOuter};
+use crate::streams::keyed::{hash_key, Fold, FoldByKey, FoldMerge, GroupByKey, Reduce, ReduceByKey};
+use crate::streams::limit::Limit;
+use crate::streams::on_error::{ErrorPolicy, OnError};
+use crate::streams::retry::{RetryPolicy, ThenRetry};
+use crate::streams::sort::{RangePartition, RangeSort, SortRun};
+use crate::streams::spill::{Spill, SpillBuffer, SpillFoldByKey, SpillGroupByKey, SpillReduceByKey};
+use crate::streams::state::{KeyContext, Process, TimedProcess};
+use crate::streams::top_k::{BoundedHeap, IntoSorted, TopK};
+use crate::streams::window::{KeyedWindow, LocalWindow, Window, Windowed};
 use crate::SandData;
 
 pub struct PStream<St> {
@@ -55,6 +79,31 @@ where
         PStream::new(self.fb, then)
     }
 
+    /// Same as `then`, but up to `limit` futures are run concurrently on each worker, outputs are emitted in the
+    /// order of items;
+    pub fn then_buffered<Fut, F>(self, limit: usize, f: F) -> PStream<Buffered<Map<St, F>>>
+    where
+        F: FnMut(St::Item) -> Fut,
+        Fut: Future,
+        Self: Sized,
+    {
+        assert!(limit > 0, "limit should be larger than 0");
+        let buffered = self.stream.map(f).buffered(limit);
+        PStream::new(self.fb, buffered)
+    }
+
+    /// Same as `then_buffered`, but outputs are emitted in the order their futures complete;
+    pub fn then_unordered<Fut, F>(self, limit: usize, f: F) -> PStream<BufferUnordered<Map<St, F>>>
+    where
+        F: FnMut(St::Item) -> Fut,
+        Fut: Future,
+        Self: Sized,
+    {
+        assert!(limit > 0, "limit should be larger than 0");
+        let unordered = self.stream.map(f).buffer_unordered(limit);
+        PStream::new(self.fb, unordered)
+    }
+
     pub fn flat_map<U, F>(self, f: F) -> PStream<FlatMap<St, U, F>>
     where
         F: FnMut(St::Item) -> U,
@@ -83,9 +132,553 @@ where
     where
         R: FnMut(&Item) -> u64 + Send + Unpin + 'static,
     {
-        let (senders, receiver) = self.fb.alloc_local::<Item>();
+        let capacity = self.fb.get_channel_capacity();
+        self.exchange_with_capacity(route, capacity)
+    }
+
+    /// Same as `exchange`, but channels to workers are created with `capacity` instead of the default capacity of
+    /// the job, e.g. a small one for large items;
+    pub fn exchange_with_capacity<R>(self, route: R, capacity: usize) -> InputStream<Item>
+    where
+        R: FnMut(&Item) -> u64 + Send + Unpin + 'static,
+    {
+        let (senders, receiver) = self.fb.alloc_local_with_capacity::<Item>(capacity);
         let st = self.stream.select_forward(SelectSink::new(senders, route));
-        self.fb.add_stage(st);
+        self.fb.add_named_stage("exchange", st);
+        PStream::new(self.fb, receiver)
+    }
+
+    /// Keep ok items on which `predicate` returns true;
+    pub fn filter<P>(self, predicate: P) -> PStream<Filter<Si, P>>
+    where
+        P: FnMut(&Item) -> bool,
+    {
+        PStream::new(self.fb, Filter::new(self.stream, predicate))
+    }
+
+    /// Map ok items by `f` and keep the `Some` outputs;
+    pub fn filter_map<U, F>(self, f: F) -> PStream<FilterMap<Si, F>>
+    where
+        F: FnMut(Item) -> Option<U>,
+    {
+        PStream::new(self.fb, FilterMap::new(self.stream, f))
+    }
+
+    /// Same as `filter`, but errors of `predicate` are emitted as error items;
+    pub fn try_filter<P>(self, predicate: P) -> PStream<TryFilter<Si, P>>
+    where
+        P: FnMut(&Item) -> Result<bool, FError>,
+    {
+        PStream::new(self.fb, TryFilter::new(self.stream, predicate))
+    }
+
+    /// Keep ok items of each worker until `predicate` returns false, the rest are drained and dropped;
+    pub fn take_while<P>(self, predicate: P) -> PStream<TakeWhile<Si, P>>
+    where
+        P: FnMut(&Item) -> bool,
+    {
+        PStream::new(self.fb, TakeWhile::new(self.stream, predicate))
+    }
+
+    /// Drop ok items of each worker until `predicate` returns false;
+    pub fn skip_while<P>(self, predicate: P) -> PStream<SkipWhile<Si, P>>
+    where
+        P: FnMut(&Item) -> bool,
+    {
+        PStream::new(self.fb, SkipWhile::new(self.stream, predicate))
+    }
+
+    /// Map ok items of each worker by `f` with a state starting from `init`, once `f` returns `None` the rest are
+    /// drained and dropped;
+    pub fn scan<S, U, F>(self, init: S, f: F) -> PStream<Scan<Si, S, F>>
+    where
+        F: FnMut(&mut S, Item) -> Option<U>,
+    {
+        PStream::new(self.fb, Scan::new(self.stream, init, f))
+    }
+
+    /// Group ok items of each worker into vectors of `size` items, the last one may be smaller;
+    pub fn chunks(self, size: usize) -> PStream<Chunks<Si, Item>> {
+        assert!(size > 0, "chunk size should be larger than 0");
+        PStream::new(self.fb, Chunks::new(self.stream, size))
+    }
+
+    /// Pair ok items with their indexes on this worker;
+    pub fn enumerate(self) -> PStream<Enumerate<Si>> {
+        PStream::new(self.fb, Enumerate::new(self.stream))
+    }
+
+    /// Emit at most `n` ok items by all workers, sources of the job are stopped once `n` items are emitted, and the
+    /// job finishes without error after items left in stages are drained;
+    pub fn limit(self, n: u64) -> PStream<Limit<Si>> {
+        let remaining = self.fb.alloc_shared(|| AtomicU64::new(n));
+        let error_hook = self.fb.get_error_hook().clone();
+        PStream::new(self.fb, Limit::new(self.stream, remaining, error_hook))
+    }
+
+    /// Handle error items by `policy` instead of aborting the job, e.g. skip them or send them to dead letters;
+    pub fn on_error(self, policy: ErrorPolicy) -> PStream<OnError<Si>> {
+        let (job_id, worker_index) = (self.fb.get_job_id(), self.fb.get_index() as u32);
+        PStream::new(self.fb, OnError::new(job_id, worker_index, self.stream, policy))
+    }
+
+    /// Same as `then`, but `f` is called again with a copy of the item if its future fails, until it succeeds or
+    /// `policy` gives up, then the last error is emitted; backoffs between retries are timed by the spawner of the job;
+    pub fn then_retry<U, Fut, F>(self, f: F, policy: RetryPolicy) -> PStream<ThenRetry<Si, Fut, F, Item>>
+    where
+        Item: Clone,
+        F: FnMut(Item) -> Fut,
+        Fut: Future<Output = Result<U, FError>>,
+    {
+        let spawner = self.fb.get_spawner().clone();
+        PStream::new(self.fb, ThenRetry::new(self.stream, f, policy, spawner))
+    }
+
+    /// Fork the stream into `n` branches, each branch gets a copy of every item; all branches should be consumed, a
+    /// branch which is full blocks the others, and a branch which is dropped fails the split with `FError::ChSend`,
+    /// which aborts the job;
+    pub fn split(self, n: usize) -> Vec<InputStream<Item>>
+    where
+        Item: Clone,
+    {
+        assert!(n > 0, "split into no branch;");
+        let mut senders = Vec::with_capacity(n);
+        let mut branches = Vec::with_capacity(n);
+        for _ in 0..n {
+            let (tx, rx) = self.fb.alloc_channel::<Item>();
+            senders.push(tx);
+            branches.push(PStream::new(self.fb.clone(), rx));
+        }
+        let st = self.stream.select_forward(BroadcastSink::new(senders));
+        self.fb.add_named_stage("split", st);
+        branches
+    }
+
+    /// Merge items of this stream and `other` into one stream, items of each stream are forwarded by its own stage;
+    pub fn union<S>(self, other: PStream<S>) -> InputStream<Item>
+    where
+        S: Stream<Item = Result<Item, FError>> + Send + 'static,
+    {
+        assert_eq!(self.fb.get_index(), other.fb.get_index(), "can't union streams of different workers;");
+        let (tx, rx) = self.fb.alloc_channel::<Item>();
+        self.fb
+            .add_named_stage("union", self.stream.forward(tx.clone()));
+        other.fb.add_named_stage("union", other.stream.forward(tx));
+        PStream::new(self.fb, rx)
+    }
+
+    /// Send a copy of each item to every worker;
+    pub fn broadcast(self) -> InputStream<Item>
+    where
+        Item: Clone,
+    {
+        let (senders, receiver) = self.fb.alloc_local::<Item>();
+        let st = self.stream.select_forward(BroadcastSink::new(senders));
+        self.fb.add_named_stage("broadcast", st);
         PStream::new(self.fb, receiver)
     }
+
+    /// Route items to workers by the hash of their keys, and fold items of the same key from the value created by
+    /// `init`, emit one `(key, value)` for each key on its owner worker after the upstream is exhausted;
+    pub fn fold_by_key<K, V, KF, I, F>(self, key_fn: KF, init: I, fold: F) -> PStream<FoldByKey<K, Item, V, I, F>>
+    where
+        K: SandData + Hash + Eq,
+        KF: FnMut(&Item) -> K + Send + 'static,
+        I: FnMut() -> V,
+        F: FnMut(V, Item) -> V,
+    {
+        let exchanged = self.exchange_by_key(key_fn);
+        PStream::new(exchanged.fb, GroupByKey::new(exchanged.stream, Fold::new(init, fold)))
+    }
+
+    /// Same as `fold_by_key`, but the first item of a key is used as the initial value;
+    pub fn reduce_by_key<K, KF, F>(self, key_fn: KF, reduce: F) -> PStream<ReduceByKey<K, Item, F>>
+    where
+        K: SandData + Hash + Eq,
+        KF: FnMut(&Item) -> K + Send + 'static,
+        F: FnMut(Item, Item) -> Item,
+    {
+        let exchanged = self.exchange_by_key(key_fn);
+        PStream::new(exchanged.fb, GroupByKey::new(exchanged.stream, Reduce::new(reduce)))
+    }
+
+    /// Same as `fold_by_key`, but accumulators are written to disk as runs sorted by key once the memory budget of
+    /// the job is exceeded, runs are merged after the upstream is exhausted, and accumulators of a key in different
+    /// runs are merged by `merge`;
+    pub fn fold_by_key_spillable<K, V, KF, I, F, M>(
+        self, key_fn: KF, init: I, fold: F, merge: M,
+    ) -> PStream<SpillFoldByKey<K, Item, V, I, F, M>>
+    where
+        K: SandData + Hash + Ord + Spill,
+        V: Spill,
+        KF: FnMut(&Item) -> K + Send + 'static,
+        I: FnMut() -> V,
+        F: FnMut(V, Item) -> V,
+        M: FnMut(V, V) -> V,
+    {
+        let exchanged = self.exchange_by_key(key_fn);
+        let budget = exchanged.fb.get_memory_budget().cloned();
+        let worker_index = exchanged.fb.get_index();
+        let agg = FoldMerge::new(Fold::new(init, fold), merge);
+        PStream::new(exchanged.fb, SpillGroupByKey::new(exchanged.stream, agg, budget, worker_index))
+    }
+
+    /// Same as `reduce_by_key`, but spill like `fold_by_key_spillable`, accumulators in different runs are merged
+    /// by `reduce`;
+    pub fn reduce_by_key_spillable<K, KF, F>(self, key_fn: KF, reduce: F) -> PStream<SpillReduceByKey<K, Item, F>>
+    where
+        K: SandData + Hash + Ord + Spill,
+        Item: Spill,
+        KF: FnMut(&Item) -> K + Send + 'static,
+        F: FnMut(Item, Item) -> Item,
+    {
+        let exchanged = self.exchange_by_key(key_fn);
+        let budget = exchanged.fb.get_memory_budget().cloned();
+        let worker_index = exchanged.fb.get_index();
+        PStream::new(exchanged.fb, SpillGroupByKey::new(exchanged.stream, Reduce::new(reduce), budget, worker_index))
+    }
+
+    /// Route items to workers by the hash of their keys, so items of a key can be processed with states of the key;
+    pub fn key_by<K, KF>(self, key_fn: KF) -> KeyedStream<K, Item>
+    where
+        K: SandData + Hash,
+        KF: FnMut(&Item) -> K + Send + 'static,
+    {
+        KeyedStream { input: self.exchange_by_key(key_fn) }
+    }
+
+    /// Route items to workers by the hash of their keys, and emit the first item of each key, all keys seen are kept
+    /// in memory until the upstream is exhausted;
+    pub fn distinct_by<K, KF>(self, key_fn: KF) -> PStream<Distinct<K, Item>>
+    where
+        K: SandData + Hash + Eq + Clone,
+        KF: FnMut(&Item) -> K + Send + 'static,
+    {
+        let exchanged = self.exchange_by_key(key_fn);
+        PStream::new(exchanged.fb, Distinct::new(exchanged.stream))
+    }
+
+    /// Same as `distinct_by`, but each worker keeps at most `capacity` keys, and keys seen `ttl` ago are forgotten,
+    /// the oldest keys are forgotten first, so the same key may be emitted again; it fits unbounded sources;
+    pub fn distinct_by_bounded<K, KF>(self, key_fn: KF, capacity: usize, ttl: Option<Duration>) -> PStream<Distinct<K, Item>>
+    where
+        K: SandData + Hash + Eq + Clone,
+        KF: FnMut(&Item) -> K + Send + 'static,
+    {
+        assert!(capacity > 0, "capacity should be larger than 0");
+        let exchanged = self.exchange_by_key(key_fn);
+        PStream::new(exchanged.fb, Distinct::bounded(exchanged.stream, capacity, ttl))
+    }
+
+    /// Join items with items of `other` by key, both streams are routed to workers by the hash of their keys, and
+    /// `(left, right)` is emitted for each matched pair;
+    pub fn join<K, R, S, LK, RK>(self, other: PStream<S>, left_key: LK, right_key: RK) -> PStream<HashJoin<K, Item, R, Inner>>
+    where
+        K: SandData + Hash + Eq,
+        Item: Clone,
+        R: SandData + Clone,
+        S: Stream<Item = Result<R, FError>> + Send + 'static,
+        LK: FnMut(&Item) -> K + Send + 'static,
+        RK: FnMut(&R) -> K + Send + 'static,
+    {
+        self.hash_join(other, left_key, right_key)
+    }
+
+    /// Same as `join`, but `(left, None)` is also emitted for each left item without match;
+    pub fn left_join<K, R, S, LK, RK>(
+        self, other: PStream<S>, left_key: LK, right_key: RK,
+    ) -> PStream<HashJoin<K, Item, R, LeftOuter>>
+    where
+        K: SandData + Hash + Eq,
+        Item: Clone,
+        R: SandData + Clone,
+        S: Stream<Item = Result<R, FError>> + Send + 'static,
+        LK: FnMut(&Item) -> K + Send + 'static,
+        RK: FnMut(&R) -> K + Send + 'static,
+    {
+        self.hash_join(other, left_key, right_key)
+    }
+
+    fn hash_join<K, R, S, LK, RK, M>(self, other: PStream<S>, left_key: LK, right_key: RK) -> PStream<HashJoin<K, Item, R, M>>
+    where
+        K: SandData + Hash + Eq,
+        R: SandData,
+        S: Stream<Item = Result<R, FError>> + Send + 'static,
+        LK: FnMut(&Item) -> K + Send + 'static,
+        RK: FnMut(&R) -> K + Send + 'static,
+        M: JoinMode<Item, R>,
+    {
+        let left = self.exchange_by_key(left_key);
+        let right = other.exchange_by_key(right_key);
+        PStream::new(left.fb, HashJoin::new(left.stream, right.stream))
+    }
+
+    fn exchange_by_key<K, KF>(self, mut key_fn: KF) -> InputStream<(K, Item)>
+    where
+        K: SandData + Hash,
+        KF: FnMut(&Item) -> K + Send + 'static,
+    {
+        self.map(move |item| item.map(|item| (key_fn(&item), item)))
+            .exchange(|(key, _): &(K, Item)| hash_key(key))
+    }
+
+    /// Sort items of all workers by key: keys are sampled to split them into ranges, each worker sorts items of
+    /// one range, and runs of workers are emitted in order, so results of the job are totally ordered if the sort is
+    /// only followed by operators which keep the order, e.g. `map` or `filter`;
+    pub fn sort_by_key<K, KF>(self, key_fn: KF) -> PStream<SortRun<K, Item>>
+    where
+        K: SandData + Ord + Clone,
+        KF: FnMut(&Item) -> K + Send + 'static,
+    {
+        let peers = self.fb.get_local_peers();
+        let shared = self.fb.alloc_shared(|| RangeSort::new(peers));
+        let partitioned = PStream::new(self.fb, RangePartition::new(self.stream, key_fn, shared.clone()));
+        let range = shared.clone();
+        let exchanged = partitioned.exchange(move |(key, _): &(K, Item)| range.partition(key));
+        let worker_index = exchanged.fb.get_index();
+        PStream::new(exchanged.fb, SortRun::new(exchanged.stream, worker_index, shared))
+    }
+
+    /// Same as `sort_by_key`, but buffered items are written to disk as runs sorted by key once the memory budget of
+    /// the job is exceeded, and runs are merged when they are emitted;
+    pub fn sort_by_key_spillable<K, KF>(self, key_fn: KF) -> PStream<SortRun<K, Item, SpillBuffer<K, Item>>>
+    where
+        K: SandData + Ord + Clone + Spill,
+        Item: Spill,
+        KF: FnMut(&Item) -> K + Send + 'static,
+    {
+        let peers = self.fb.get_local_peers();
+        let shared = self.fb.alloc_shared(|| RangeSort::new(peers));
+        let budget = self.fb.get_memory_budget().cloned();
+        let worker_index = self.fb.get_index();
+        let buffer = SpillBuffer::new(budget.clone(), worker_index);
+        let partition = RangePartition::with_buffer(self.stream, key_fn, shared.clone(), buffer);
+        let partitioned = PStream::new(self.fb, partition);
+        let range = shared.clone();
+        let exchanged = partitioned.exchange(move |(key, _): &(K, Item)| range.partition(key));
+        let buffer = SpillBuffer::new(budget, worker_index);
+        PStream::new(exchanged.fb, SortRun::with_buffer(exchanged.stream, worker_index, shared, buffer))
+    }
+
+    /// Fold items of each worker from `init` into a partial result, then gather all partial results to the
+    /// first worker and merge them, so exactly one final result is emitted by the whole job; a job of one worker
+    /// merges its partial result without exchange;
+    pub fn aggregate<A, F, M>(self, init: A, fold: F, merge: M) -> PStream<Aggregate<A, M>>
+    where
+        A: SandData,
+        F: FnMut(A, Item) -> A + Send + 'static,
+        M: FnMut(A, A) -> A,
+    {
+        let partial = Partial::new(self.stream, init, fold);
+        if self.fb.get_local_peers() == 1 {
+            return PStream::new(self.fb, Merge::new(Gather::Local(partial.boxed()), merge));
+        }
+        let gathered = PStream::new(self.fb, partial).exchange(|_: &A| 0);
+        PStream::new(gathered.fb, Merge::new(Gather::Channel(gathered.stream), merge))
+    }
+
+    /// Count items of all workers;
+    pub fn count(self) -> PStream<Sum<u64>> {
+        self.aggregate(0, |count, _| count + 1, add as fn(u64, u64) -> u64)
+    }
+
+    /// Sum items of all workers;
+    pub fn sum(self) -> PStream<Sum<Item>>
+    where
+        Item: Add<Output = Item> + Default,
+    {
+        self.aggregate(Item::default(), add, add as fn(Item, Item) -> Item)
+    }
+
+    /// Emit at most `k` items with the largest keys of all workers in descending order of keys, each worker keeps
+    /// its own top `k` items, which are merged on the first worker;
+    pub fn top_k<K, KF>(self, k: usize, mut key_fn: KF) -> PStream<TopK<K, Item>>
+    where
+        K: SandData + Ord,
+        KF: FnMut(&Item) -> K + Send + 'static,
+    {
+        assert!(k > 0, "k should be larger than 0");
+        let fold = move |mut heap: BoundedHeap<K, Item>, item: Item| {
+            let key = key_fn(&item);
+            heap.push(key, item);
+            heap
+        };
+        let merged = self.aggregate(BoundedHeap::new(k), fold, BoundedHeap::merge as fn(_, _) -> _);
+        PStream::new(merged.fb, IntoSorted::new(merged.stream))
+    }
+
+    /// Group items of each worker into windows by the time they arrive, each window is emitted once it ends;
+    pub fn window(self, window: Window) -> PStream<LocalWindow<Si, Item>>
+    where
+        Item: Clone,
+    {
+        let spawner = self.fb.get_spawner().clone();
+        let unkeyed = self
+            .stream
+            .map((|item: Result<Item, FError>| item.map(|item| ((), item))) as fn(_) -> _);
+        PStream::new(self.fb, Windowed::new(unkeyed, window, spawner))
+    }
+
+    /// Route items to workers by the hash of their keys, and group items of each key into windows by the time they
+    /// arrive, each window is emitted once it ends;
+    pub fn window_by_key<K, KF>(self, key_fn: KF, window: Window) -> PStream<KeyedWindow<K, Item>>
+    where
+        K: SandData + Hash + Eq + Clone,
+        Item: Clone,
+        KF: FnMut(&Item) -> K + Send + 'static,
+    {
+        let exchanged = self.exchange_by_key(key_fn);
+        let spawner = exchanged.fb.get_spawner().clone();
+        let keyed = exchanged.stream.map(Ok as fn(_) -> _);
+        PStream::new(exchanged.fb, Windowed::new(keyed, window, spawner))
+    }
+
+    /// Attach event time in milliseconds to items by `ts_fn`, and emit watermarks which allow items of each worker to
+    /// be out of order by `max_out_of_orderness`;
+    pub fn assign_timestamps<F>(self, ts_fn: F, max_out_of_orderness: Duration) -> TimedStream<AssignTimestamps<Si, F>>
+    where
+        F: FnMut(&Item) -> u64,
+    {
+        let delay = max_out_of_orderness.as_millis() as u64;
+        TimedStream { input: PStream::new(self.fb, AssignTimestamps::new(self.stream, ts_fn, delay)) }
+    }
+
+    /// Feed items into the loop `body` round by round. The `body` returns two streams: items of the first one are fed
+    /// back to the loop head of this worker, and items of the second one leave the loop and are returned.
+    ///
+    /// Items fed back during a round are emitted in the next round after all local peers finish the round, the loop
+    /// stops when no item is fed back in a round, or after `max_rounds` rounds, items still fed back then are dropped.
+    ///
+    /// A round is finished when no item is left in channels or held by operators of the body, so operators which
+    /// buffer items across polls (e.g. keyed folds or aggregates) should not be used inside the body; nested iteration
+    /// is not supported;
+    pub fn iterate<C, E, B>(self, max_rounds: usize, body: B) -> PStream<E>
+    where
+        B: FnOnce(InputStream<Item>) -> (PStream<C>, PStream<E>),
+        C: Stream<Item = Result<Item, FError>> + Send + 'static,
+    {
+        assert!(max_rounds > 0, "iteration needs at least one round;");
+        let PStream { stream, fb } = self;
+        let peers = fb.get_local_peers();
+        let scope = fb.alloc_shared(|| LoopScope::new(peers, max_rounds));
+        let inner = fb.in_scope(scope.clone());
+        let (head_tx, head_rx) = inner.alloc_channel::<Item>();
+        let (feedback_tx, feedback_rx) = inner.alloc_channel::<Item>();
+        fb.add_named_stage("iterate", LoopHead::new(fb.get_index(), scope, stream, feedback_rx, head_tx));
+
+        let (feedback, exit) = body(PStream::new(inner, head_rx));
+        let feedback_fb = feedback.fb.clone();
+        feedback_fb.add_named_stage("iterate", feedback.forward(feedback_tx));
+        PStream::new(fb, exit.stream)
+    }
+}
+
+/// Items routed to workers by their keys;
+pub struct KeyedStream<K, T> {
+    input: InputStream<(K, T)>,
+}
+
+impl<K, T> KeyedStream<K, T>
+where
+    K: SandData + Hash + Eq + Clone,
+    T: SandData,
+{
+    /// Call `f` for each item with the context of its key, states got from the context are kept by the worker which
+    /// owns the key, and items emitted by the context are emitted in order;
+    pub fn process<U, F>(self, f: F) -> PStream<Process<K, T, U, F>>
+    where
+        F: FnMut(&mut KeyContext<'_, K, U>, T) -> Result<(), FError>,
+    {
+        let PStream { stream, fb } = self.input;
+        PStream::new(fb, Process::new(stream, f))
+    }
+}
+
+/// Items with event time and watermarks of each worker, they are only routed to workers by `exchange_timed`, which
+/// sends watermarks to all workers;
+pub struct TimedStream<St> {
+    input: PStream<St>,
+}
+
+impl<Si, T> TimedStream<Si>
+where
+    T: SandData,
+    Si: Stream<Item = Result<Timed<T>, FError>> + Send + 'static,
+{
+    /// Map items by `f`, their event times and watermarks are kept;
+    pub fn map<U, F>(self, f: F) -> TimedStream<MapTimed<Si, F>>
+    where
+        F: FnMut(T) -> U,
+    {
+        let PStream { stream, fb } = self.input;
+        TimedStream { input: PStream::new(fb, MapTimed::new(stream, f)) }
+    }
+
+    /// Route items to workers by `route`, watermarks are sent to all workers, and each worker emits the minimum
+    /// watermark of all workers except idle ones (see `JobConfig::idle_timeout`);
+    pub fn exchange_timed<R>(self, route: R) -> TimedStream<MergeWatermarks<T>>
+    where
+        R: FnMut(&T) -> u64 + Send + 'static,
+    {
+        let PStream { stream, fb } = self.input;
+        let (senders, receiver) = fb.alloc_local::<(u32, Timed<T>)>();
+        let sink = WatermarkSink::new(fb.get_index() as u32, senders, route);
+        fb.add_named_stage("exchange", stream.select_forward(sink));
+        let peers = fb.get_local_peers();
+        let idle = fb
+            .get_idle_timeout()
+            .map(|timeout| (timeout, fb.get_spawner().clone()));
+        TimedStream { input: PStream::new(fb, MergeWatermarks::new(receiver, peers, idle)) }
+    }
+
+    /// Group items of each worker into windows by event time, each window is emitted once the watermark passes its end;
+    /// items arriving after all their windows are emitted are late, they are emitted by the second stream;
+    pub fn event_window(self, window: Window) -> (PStream<LocalEventWindow<Si, T>>, InputStream<T>)
+    where
+        T: Clone,
+    {
+        let PStream { stream, fb } = self.input;
+        let (late_tx, late_rx) = fb.alloc_channel::<T>();
+        let unkeyed =
+            stream.map((|item: Result<Timed<T>, FError>| item.map(|timed| timed.map(|item| ((), item)))) as fn(_) -> _);
+        let windowed = EventWindowed::new(unkeyed, window, late_tx);
+        (PStream::new(fb.clone(), windowed), PStream::new(fb, late_rx))
+    }
+
+    /// Same as `event_window`, but items are routed to workers by the hash of their keys, and grouped into windows
+    /// of their keys;
+    pub fn event_window_by_key<K, KF>(self, mut key_fn: KF, window: Window) -> (PStream<KeyedEventWindow<K, T>>, InputStream<T>)
+    where
+        K: SandData + Hash + Eq + Clone,
+        T: Clone,
+        KF: FnMut(&T) -> K + Send + 'static,
+    {
+        let PStream { stream, fb } = self
+            .map(move |item| (key_fn(&item), item))
+            .exchange_timed(|(key, _): &(K, T)| hash_key(key))
+            .input;
+        let (late_tx, late_rx) = fb.alloc_channel::<T>();
+        let windowed = EventWindowed::new(stream, window, late_tx);
+        (PStream::new(fb.clone(), windowed), PStream::new(fb, late_rx))
+    }
+
+    /// Route items to workers by their keys, and call `on_item` for each item with its event time and the context of
+    /// its key; timers registered by the context call `on_timer` with the context of their keys and their times once
+    /// the watermark reaches them, so timers left are all fired by the final watermark. Items behind the watermark are
+    /// still processed, they can be told by `KeyContext::current_watermark`;
+    pub fn process_timed<K, KF, U, F, OT>(
+        self, mut key_fn: KF, on_item: F, on_timer: OT,
+    ) -> PStream<TimedProcess<K, T, U, F, OT>>
+    where
+        K: SandData + Hash + Eq + Clone,
+        KF: FnMut(&T) -> K + Send + 'static,
+        F: FnMut(&mut KeyContext<'_, K, U>, u64, T) -> Result<(), FError>,
+        OT: FnMut(&mut KeyContext<'_, K, U>, u64) -> Result<(), FError>,
+    {
+        let PStream { stream, fb } = self
+            .map(move |item| (key_fn(&item), item))
+            .exchange_timed(|(key, _): &(K, T)| hash_key(key))
+            .input;
+        PStream::new(fb, TimedProcess::new(stream, on_item, on_timer))
+    }
 }
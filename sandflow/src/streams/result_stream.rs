@@ -1,36 +1,117 @@
+use std::future::Future;
 use std::pin::Pin;
 use std::sync::Arc;
 use std::task::{Context, Poll};
 
 use futures::channel::mpsc::Receiver;
 use futures::{ready, Stream};
-use pin_project_lite::pin_project;
 
-use crate::{ErrorHook, FError};
+use crate::errors::JobError;
+use crate::ErrorHook;
 
-pin_project! {
-    pub struct ResultStream<T> {
-        error_hook: Arc<ErrorHook>,
-        #[pin]
-        rx: Receiver<T>
-    }
+/// Results of a job, the job is cancelled once it is dropped;
+pub struct ResultStream<T> {
+    job_id: u64,
+    error_hook: Arc<ErrorHook>,
+    rx: Receiver<T>,
+    // the stream ends after the error of the job is returned;
+    is_failed: bool,
 }
 
 impl<T> ResultStream<T> {
-    pub fn new(error_hook: Arc<ErrorHook>, rx: Receiver<T>) -> Self {
-        ResultStream { error_hook, rx }
+    pub fn new(job_id: u64, error_hook: Arc<ErrorHook>, rx: Receiver<T>) -> Self {
+        ResultStream { job_id, error_hook, rx, is_failed: false }
+    }
+
+    pub fn job_handle(&self) -> JobHandle {
+        JobHandle { job_id: self.job_id, error_hook: self.error_hook.clone() }
     }
 }
 
 impl<T> Stream for ResultStream<T> {
-    type Item = Result<T, FError>;
+    type Item = Result<T, JobError>;
 
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        if let Some(err) = self.error_hook.take_error() {
-            return Poll::Ready(Some(Err(err)));
+        let this = self.get_mut();
+        if this.is_failed {
+            return Poll::Ready(None);
+        }
+        if this.error_hook.has_error() {
+            // errors of other tasks are collected until they are all aborted;
+            if this.error_hook.is_collecting() {
+                ready!(this.error_hook.poll_finished(cx));
+            }
+            if let Some(err) = this.error_hook.take_error() {
+                this.is_failed = true;
+                return Poll::Ready(Some(Err(err)));
+            }
+        }
+        match ready!(Pin::new(&mut this.rx).poll_next(cx)) {
+            Some(item) => Poll::Ready(Some(Ok(item))),
+            None => {
+                // an error may be set after it's checked above, so it's checked again after all tasks are finished;
+                ready!(this.error_hook.poll_finished(cx));
+                match this.error_hook.take_error() {
+                    Some(err) => {
+                        this.is_failed = true;
+                        Poll::Ready(Some(Err(err)))
+                    }
+                    None => Poll::Ready(None),
+                }
+            }
+        }
+    }
+}
+
+impl<T> Drop for ResultStream<T> {
+    fn drop(&mut self) {
+        if !self.error_hook.is_finished() {
+            debug!("cancel job({}) as its results are dropped;", self.job_id);
+            self.error_hook.cancel();
         }
-        let this = self.project();
-        let a = ready!(this.rx.poll_next(cx));
-        Poll::Ready(a.map(|y| Ok(y)))
+    }
+}
+
+/// Handle to control a running job;
+#[derive(Clone)]
+pub struct JobHandle {
+    job_id: u64,
+    error_hook: Arc<ErrorHook>,
+}
+
+impl JobHandle {
+    pub fn job_id(&self) -> u64 {
+        self.job_id
+    }
+
+    /// Abort the source and all stages of the job;
+    pub fn cancel(&self) {
+        self.error_hook.cancel();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.error_hook.is_cancelled()
+    }
+
+    /// If all tasks of the job are finished, aborted tasks are also finished;
+    pub fn is_finished(&self) -> bool {
+        self.error_hook.is_finished()
+    }
+
+    /// Wait until all tasks of the job are finished;
+    pub fn join(&self) -> JoinJob {
+        JoinJob { error_hook: self.error_hook.clone() }
+    }
+}
+
+pub struct JoinJob {
+    error_hook: Arc<ErrorHook>,
+}
+
+impl Future for JoinJob {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.error_hook.poll_finished(cx)
     }
 }
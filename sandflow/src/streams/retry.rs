@@ -0,0 +1,164 @@
+use std::collections::hash_map::RandomState;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use futures::{ready, Stream};
+use pin_project_lite::pin_project;
+use sandflow_executor::Spawner;
+
+use crate::errors::FError;
+
+type Retryable = Arc<dyn Fn(&FError) -> bool + Send + Sync + 'static>;
+
+/// How a failed future is retried, the n-th retry waits `initial_backoff * multiplier^(n-1)` capped by `max_backoff`,
+/// and reduced by a random ratio up to `jitter`;
+#[derive(Clone)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    multiplier: f64,
+    jitter: f64,
+    retryable: Retryable,
+}
+
+impl RetryPolicy {
+    /// Try at most `max_attempts` times, all errors are retryable by default;
+    pub fn new(max_attempts: u32) -> Self {
+        assert!(max_attempts > 0, "at least one attempt is needed");
+        Self {
+            max_attempts,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(10),
+            multiplier: 2.0,
+            jitter: 0.0,
+            retryable: Arc::new(|_| true),
+        }
+    }
+
+    pub fn initial_backoff(mut self, backoff: Duration) -> Self {
+        self.initial_backoff = backoff;
+        self
+    }
+
+    pub fn max_backoff(mut self, backoff: Duration) -> Self {
+        self.max_backoff = backoff;
+        self
+    }
+
+    pub fn multiplier(mut self, multiplier: f64) -> Self {
+        assert!(multiplier >= 1.0, "multiplier should be at least 1");
+        self.multiplier = multiplier;
+        self
+    }
+
+    /// Reduce each backoff by a random ratio in `[0, jitter)`, `jitter` should be in `[0, 1]`;
+    pub fn jitter(mut self, jitter: f64) -> Self {
+        assert!((0.0..=1.0).contains(&jitter), "jitter should be in [0, 1]");
+        self.jitter = jitter;
+        self
+    }
+
+    /// Only retry errors on which `predicate` returns true, the others fail immediately;
+    pub fn retry_if<P>(mut self, predicate: P) -> Self
+    where
+        P: Fn(&FError) -> bool + Send + Sync + 'static,
+    {
+        self.retryable = Arc::new(predicate);
+        self
+    }
+
+    fn backoff(&self, retries: u32, random: f64) -> Duration {
+        let backoff = self.initial_backoff.as_secs_f64() * self.multiplier.powi(retries as i32 - 1);
+        let backoff = backoff.min(self.max_backoff.as_secs_f64());
+        Duration::from_secs_f64(backoff * (1.0 - self.jitter * random))
+    }
+}
+
+pin_project! {
+    /// Same as `then`, but the future is recreated from a copy of the item and retried by the policy if it fails;
+    pub struct ThenRetry<St, Fut, F, T> {
+        #[pin]
+        stream: St,
+        #[pin]
+        future: Option<Fut>,
+        f: F,
+        policy: RetryPolicy,
+        spawner: Arc<dyn Spawner>,
+        item: Option<T>,
+        attempts: u32,
+        backoff: Option<BoxFuture<'static, ()>>,
+        seed: u64,
+    }
+}
+
+impl<St, Fut, F, T> ThenRetry<St, Fut, F, T> {
+    pub fn new(stream: St, f: F, policy: RetryPolicy, spawner: Arc<dyn Spawner>) -> Self {
+        let seed = RandomState::new().build_hasher().finish() | 1;
+        Self { stream, future: None, f, policy, spawner, item: None, attempts: 0, backoff: None, seed }
+    }
+}
+
+impl<St, Fut, F, T, U> Stream for ThenRetry<St, Fut, F, T>
+where
+    St: Stream<Item = Result<T, FError>>,
+    F: FnMut(T) -> Fut,
+    Fut: Future<Output = Result<U, FError>>,
+    T: Clone,
+{
+    type Item = Result<U, FError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        loop {
+            if let Some(backoff) = this.backoff.as_mut() {
+                ready!(backoff.as_mut().poll(cx));
+                *this.backoff = None;
+                let item = this.item.clone().expect("retried item lost;");
+                this.future.set(Some((this.f)(item)));
+            }
+
+            if let Some(future) = this.future.as_mut().as_pin_mut() {
+                let result = ready!(future.poll(cx));
+                this.future.set(None);
+                *this.attempts += 1;
+                match result {
+                    Ok(output) => {
+                        *this.item = None;
+                        return Poll::Ready(Some(Ok(output)));
+                    }
+                    Err(e) if *this.attempts < this.policy.max_attempts && (this.policy.retryable)(&e) => {
+                        // xorshift is enough for jitter;
+                        *this.seed ^= *this.seed << 13;
+                        *this.seed ^= *this.seed >> 7;
+                        *this.seed ^= *this.seed << 17;
+                        let random = (*this.seed >> 11) as f64 / (1u64 << 53) as f64;
+                        let backoff = this.policy.backoff(*this.attempts, random);
+                        debug!("retry after {:?} as attempt {} failed: {};", backoff, this.attempts, e);
+                        *this.backoff = Some(this.spawner.sleep(backoff));
+                    }
+                    Err(e) => {
+                        *this.item = None;
+                        return Poll::Ready(Some(Err(e)));
+                    }
+                }
+                continue;
+            }
+
+            match ready!(this.stream.as_mut().poll_next(cx)) {
+                Some(Ok(item)) => {
+                    *this.attempts = 0;
+                    this.future.set(Some((this.f)(item.clone())));
+                    *this.item = Some(item);
+                }
+                Some(Err(e)) => return Poll::Ready(Some(Err(e))),
+                None => return Poll::Ready(None),
+            }
+        }
+    }
+}
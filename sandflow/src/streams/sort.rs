@@ -0,0 +1,257 @@
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::task::{Context, Poll, Waker};
+
+use futures::{ready, Stream};
+use pin_project_lite::pin_project;
+
+use crate::errors::FError;
+use crate::stages::source::StageInput;
+use crate::streams::spill::RunBuffer;
+
+/// Max count of keys sampled by each worker;
+const SAMPLES_PER_WORKER: usize = 128;
+
+/// State of a sort shared by all local peers: key samples to compute range boundaries, and the turn of workers to
+/// emit their sorted runs;
+pub struct RangeSort<K> {
+    peers: usize,
+    boundaries: OnceLock<Vec<K>>,
+    state: Mutex<SortState<K>>,
+}
+
+struct SortState<K> {
+    // sampled keys with their weights, a key sampled from more items has larger weight;
+    samples: Vec<(K, f64)>,
+    reported: usize,
+    turn: usize,
+    wakers: Vec<Waker>,
+}
+
+impl<K: Ord + Clone> RangeSort<K> {
+    pub fn new(peers: usize) -> Self {
+        let state = SortState { samples: vec![], reported: 0, turn: 0, wakers: vec![] };
+        Self { peers, boundaries: OnceLock::new(), state: Mutex::new(state) }
+    }
+
+    /// Index of the worker which owns the range of `key`, it's only valid after boundaries are computed;
+    pub fn partition(&self, key: &K) -> u64 {
+        let boundaries = self
+            .boundaries
+            .get()
+            .expect("range boundaries are not computed;");
+        boundaries.partition_point(|b| b < key) as u64
+    }
+
+    /// Report samples of a worker, boundaries are computed once all workers reported;
+    fn report(&self, samples: Vec<(K, f64)>) {
+        let mut state = self.state.lock().expect("lock sort state fail;");
+        state.samples.extend(samples);
+        state.reported += 1;
+        if state.reported == self.peers {
+            let mut samples = std::mem::take(&mut state.samples);
+            samples.sort_by(|a, b| a.0.cmp(&b.0));
+            let total = samples.iter().map(|(_, w)| w).sum::<f64>();
+            let mut boundaries = Vec::with_capacity(self.peers - 1);
+            let mut acc = 0.0;
+            for (key, weight) in samples {
+                acc += weight;
+                while boundaries.len() < self.peers - 1 && acc >= total * (boundaries.len() + 1) as f64 / self.peers as f64 {
+                    boundaries.push(key.clone());
+                }
+            }
+            trace!("range boundaries of sort are computed from {} samples;", total);
+            let _ = self.boundaries.set(boundaries);
+            Self::wake(&mut state);
+        }
+    }
+
+    fn poll_boundaries(&self, cx: &mut Context<'_>) -> Poll<()> {
+        if self.boundaries.get().is_some() {
+            return Poll::Ready(());
+        }
+        let mut state = self.state.lock().expect("lock sort state fail;");
+        if self.boundaries.get().is_some() {
+            Poll::Ready(())
+        } else {
+            state.wakers.push(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+
+    fn poll_turn(&self, worker_index: usize, cx: &mut Context<'_>) -> Poll<()> {
+        let mut state = self.state.lock().expect("lock sort state fail;");
+        if state.turn == worker_index {
+            Poll::Ready(())
+        } else {
+            state.wakers.push(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+
+    fn pass_turn(&self) {
+        let mut state = self.state.lock().expect("lock sort state fail;");
+        state.turn += 1;
+        Self::wake(&mut state);
+    }
+
+    fn wake(state: &mut SortState<K>) {
+        for waker in state.wakers.drain(..) {
+            waker.wake();
+        }
+    }
+}
+
+/// Sample keys of a stream with unknown length: every `step`-th key is kept, and every other sample is dropped
+/// with `step` doubled once too many samples are kept, so each sample stands for `step` items;
+struct Sampler<K> {
+    step: usize,
+    count: usize,
+    samples: Vec<K>,
+}
+
+impl<K: Clone> Sampler<K> {
+    fn new() -> Self {
+        Self { step: 1, count: 0, samples: vec![] }
+    }
+
+    fn offer(&mut self, key: &K) {
+        if self.count % self.step == 0 {
+            self.samples.push(key.clone());
+            if self.samples.len() >= SAMPLES_PER_WORKER * 2 {
+                let mut index = 0;
+                self.samples.retain(|_| {
+                    index += 1;
+                    index % 2 == 1
+                });
+                self.step *= 2;
+            }
+        }
+        self.count += 1;
+    }
+
+    fn into_weighted(self) -> Vec<(K, f64)> {
+        let step = self.step as f64;
+        self.samples.into_iter().map(|key| (key, step)).collect()
+    }
+}
+
+pin_project! {
+    /// Collect all items of the upstream with their keys into `buffer`, and sample the keys to compute range
+    /// boundaries, items are emitted after boundaries are computed;
+    pub struct RangePartition<St, K, T, KF, B: RunBuffer<K, T> = Vec<(K, T)>> {
+        #[pin]
+        stream: St,
+        key_fn: KF,
+        buffer: Option<B>,
+        sampler: Option<Sampler<K>>,
+        output: Option<B::Sorted>,
+        shared: Arc<RangeSort<K>>,
+    }
+}
+
+impl<St, K: Ord + Clone, T, KF> RangePartition<St, K, T, KF> {
+    pub fn new(stream: St, key_fn: KF, shared: Arc<RangeSort<K>>) -> Self {
+        Self::with_buffer(stream, key_fn, shared, vec![])
+    }
+}
+
+impl<St, K: Clone, T, KF, B: RunBuffer<K, T>> RangePartition<St, K, T, KF, B> {
+    pub fn with_buffer(stream: St, key_fn: KF, shared: Arc<RangeSort<K>>, buffer: B) -> Self {
+        Self { stream, key_fn, buffer: Some(buffer), sampler: Some(Sampler::new()), output: None, shared }
+    }
+}
+
+impl<St, K, T, KF, B> Stream for RangePartition<St, K, T, KF, B>
+where
+    St: Stream<Item = Result<T, FError>>,
+    K: Ord + Clone,
+    KF: FnMut(&T) -> K,
+    B: RunBuffer<K, T>,
+{
+    type Item = Result<(K, T), FError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        if let Some(output) = this.output.as_mut() {
+            return Poll::Ready(output.next());
+        }
+
+        if let Some(sampler) = this.sampler.as_mut() {
+            let buffer = this.buffer.as_mut().expect("buffer of sort lost;");
+            while let Some(item) = ready!(this.stream.as_mut().poll_next(cx)) {
+                let item = item?;
+                let key = (this.key_fn)(&item);
+                sampler.offer(&key);
+                buffer.push(key, item)?;
+            }
+            let sampler = this.sampler.take().expect("sampler of sort lost;");
+            this.shared.report(sampler.into_weighted());
+        }
+
+        ready!(this.shared.poll_boundaries(cx));
+        let buffer = this.buffer.take().expect("buffer of sort lost;");
+        let mut output = buffer.into_sorted()?;
+        let next = output.next();
+        *this.output = Some(output);
+        Poll::Ready(next)
+    }
+}
+
+/// Collect all items of the range owned by this worker into `buffer` and sort them by key, the sorted run is
+/// emitted after workers before this one have emitted their runs;
+pub struct SortRun<K, T, B: RunBuffer<K, T> = Vec<(K, T)>> {
+    input: StageInput<(K, T)>,
+    worker_index: usize,
+    buffer: Option<B>,
+    sorted: Option<B::Sorted>,
+    is_started: bool,
+    is_passed: bool,
+    shared: Arc<RangeSort<K>>,
+}
+
+impl<K: Ord, T> SortRun<K, T> {
+    pub fn new(input: StageInput<(K, T)>, worker_index: usize, shared: Arc<RangeSort<K>>) -> Self {
+        Self::with_buffer(input, worker_index, shared, vec![])
+    }
+}
+
+impl<K, T, B: RunBuffer<K, T>> SortRun<K, T, B> {
+    pub fn with_buffer(input: StageInput<(K, T)>, worker_index: usize, shared: Arc<RangeSort<K>>, buffer: B) -> Self {
+        Self { input, worker_index, buffer: Some(buffer), sorted: None, is_started: false, is_passed: false, shared }
+    }
+}
+
+impl<K, T, B: RunBuffer<K, T>> Unpin for SortRun<K, T, B> {}
+
+impl<K: Ord + Clone, T, B: RunBuffer<K, T>> Stream for SortRun<K, T, B> {
+    type Item = Result<T, FError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if let Some(buffer) = this.buffer.as_mut() {
+            while let Some((key, item)) = ready!(Pin::new(&mut this.input).poll_next(cx)) {
+                buffer.push(key, item)?;
+            }
+            let buffer = this.buffer.take().expect("buffer of sort lost;");
+            this.sorted = Some(buffer.into_sorted()?);
+        }
+
+        if !this.is_started {
+            ready!(this.shared.poll_turn(this.worker_index, cx));
+            this.is_started = true;
+        }
+
+        match this.sorted.as_mut().and_then(|sorted| sorted.next()) {
+            Some(Ok((_, item))) => Poll::Ready(Some(Ok(item))),
+            Some(Err(e)) => Poll::Ready(Some(Err(e))),
+            None => {
+                if !this.is_passed {
+                    this.is_passed = true;
+                    this.shared.pass_turn();
+                }
+                Poll::Ready(None)
+            }
+        }
+    }
+}
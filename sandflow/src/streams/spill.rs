@@ -0,0 +1,664 @@
+use std::cmp::Ordering;
+use std::collections::hash_map::{self, Entry};
+use std::collections::{BinaryHeap, HashMap};
+use std::fs::File;
+use std::hash::Hash;
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Write};
+use std::marker::PhantomData;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use futures::{ready, Stream};
+
+use crate::errors::FError;
+use crate::stages::source::StageInput;
+use crate::streams::keyed::{FoldMerge, KeyedFold, KeyedMerge, Reduce};
+
+/// Max count of spilled runs kept by an operator before they are merged into one;
+const MAX_OPEN_RUNS: usize = 64;
+
+pub type SpillFoldByKey<K, T, V, I, F, M> = SpillGroupByKey<K, T, V, FoldMerge<I, F, M>>;
+
+pub type SpillReduceByKey<K, T, F> = SpillGroupByKey<K, T, T, Reduce<F>>;
+
+/// Items which can be written to disk when the memory budget of the job is exceeded;
+pub trait Spill: Sized {
+    fn encode(&self, buf: &mut Vec<u8>);
+
+    /// Decode an item from the head of `buf`, and advance `buf` past it;
+    fn decode(buf: &mut &[u8]) -> Result<Self, FError>;
+
+    /// Estimated memory used by the item, heap memory should be included;
+    fn mem_size(&self) -> usize {
+        std::mem::size_of::<Self>()
+    }
+}
+
+fn take<'a>(buf: &mut &'a [u8], len: usize) -> Result<&'a [u8], FError> {
+    if buf.len() < len {
+        return Err(FError::StrHint("spilled record is truncated;".to_owned()));
+    }
+    let (head, tail) = buf.split_at(len);
+    *buf = tail;
+    Ok(head)
+}
+
+macro_rules! spill_number {
+    ($($t:ty),*) => {
+        $(
+            impl Spill for $t {
+                #[inline]
+                fn encode(&self, buf: &mut Vec<u8>) {
+                    buf.extend_from_slice(&self.to_le_bytes());
+                }
+
+                #[inline]
+                fn decode(buf: &mut &[u8]) -> Result<Self, FError> {
+                    let bytes = take(buf, std::mem::size_of::<$t>())?;
+                    Ok(<$t>::from_le_bytes(bytes.try_into().expect("size of number mismatch;")))
+                }
+            }
+        )*
+    };
+}
+
+spill_number!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64);
+
+impl Spill for bool {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.push(*self as u8);
+    }
+
+    fn decode(buf: &mut &[u8]) -> Result<Self, FError> {
+        Ok(u8::decode(buf)? != 0)
+    }
+}
+
+impl Spill for char {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        (*self as u32).encode(buf)
+    }
+
+    fn decode(buf: &mut &[u8]) -> Result<Self, FError> {
+        char::from_u32(u32::decode(buf)?).ok_or_else(|| FError::StrHint("invalid spilled char;".to_owned()))
+    }
+}
+
+impl Spill for () {
+    fn encode(&self, _buf: &mut Vec<u8>) {}
+
+    fn decode(_buf: &mut &[u8]) -> Result<Self, FError> {
+        Ok(())
+    }
+}
+
+impl Spill for String {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        (self.len() as u64).encode(buf);
+        buf.extend_from_slice(self.as_bytes());
+    }
+
+    fn decode(buf: &mut &[u8]) -> Result<Self, FError> {
+        let len = u64::decode(buf)? as usize;
+        let bytes = take(buf, len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|e| FError::Unknown(Box::new(e)))
+    }
+
+    fn mem_size(&self) -> usize {
+        std::mem::size_of::<Self>() + self.capacity()
+    }
+}
+
+impl<T: Spill> Spill for Vec<T> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        (self.len() as u64).encode(buf);
+        for item in self {
+            item.encode(buf);
+        }
+    }
+
+    fn decode(buf: &mut &[u8]) -> Result<Self, FError> {
+        let len = u64::decode(buf)? as usize;
+        let mut items = Vec::with_capacity(len.min(buf.len()));
+        for _ in 0..len {
+            items.push(T::decode(buf)?);
+        }
+        Ok(items)
+    }
+
+    fn mem_size(&self) -> usize {
+        std::mem::size_of::<Self>() + self.iter().map(|item| item.mem_size()).sum::<usize>()
+    }
+}
+
+impl<T: Spill> Spill for Option<T> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            Some(item) => {
+                buf.push(1);
+                item.encode(buf);
+            }
+            None => buf.push(0),
+        }
+    }
+
+    fn decode(buf: &mut &[u8]) -> Result<Self, FError> {
+        if bool::decode(buf)? {
+            Ok(Some(T::decode(buf)?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn mem_size(&self) -> usize {
+        match self {
+            Some(item) => std::mem::size_of::<Self>() - std::mem::size_of::<T>() + item.mem_size(),
+            None => std::mem::size_of::<Self>(),
+        }
+    }
+}
+
+macro_rules! spill_tuple {
+    ($($name:ident),+) => {
+        impl<$($name: Spill),+> Spill for ($($name,)+) {
+            #[allow(non_snake_case)]
+            fn encode(&self, buf: &mut Vec<u8>) {
+                let ($($name,)+) = self;
+                $($name.encode(buf);)+
+            }
+
+            fn decode(buf: &mut &[u8]) -> Result<Self, FError> {
+                Ok(($($name::decode(buf)?,)+))
+            }
+
+            #[allow(non_snake_case)]
+            fn mem_size(&self) -> usize {
+                let ($($name,)+) = self;
+                0 $(+ $name.mem_size())+
+            }
+        }
+    };
+}
+
+spill_tuple!(A);
+spill_tuple!(A, B);
+spill_tuple!(A, B, C);
+spill_tuple!(A, B, C, D);
+
+/// Memory budget shared by all spillable operators of all local workers of a job, an operator writes its state to
+/// disk once the budget is exceeded;
+pub struct MemoryBudget {
+    job_id: u64,
+    limit: usize,
+    used: AtomicUsize,
+    dir: PathBuf,
+    runs: AtomicUsize,
+}
+
+/// Sequence of run files written by all jobs of the process, so jobs with the same id never write the same file;
+static NEXT_RUN: AtomicUsize = AtomicUsize::new(0);
+
+impl MemoryBudget {
+    pub fn new(job_id: u64, limit: usize, dir: PathBuf) -> Self {
+        Self { job_id, limit, used: AtomicUsize::new(0), dir, runs: AtomicUsize::new(0) }
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    pub fn used(&self) -> usize {
+        self.used.load(AtomicOrdering::SeqCst)
+    }
+
+    /// Count of run files written so far, including runs merged from other runs;
+    pub fn spilled_runs(&self) -> usize {
+        self.runs.load(AtomicOrdering::SeqCst)
+    }
+
+    fn try_reserve(&self, bytes: usize) -> bool {
+        self.used
+            .fetch_update(AtomicOrdering::SeqCst, AtomicOrdering::SeqCst, |used| {
+                Some(used + bytes).filter(|total| *total <= self.limit)
+            })
+            .is_ok()
+    }
+
+    fn force_reserve(&self, bytes: usize) {
+        self.used.fetch_add(bytes, AtomicOrdering::SeqCst);
+    }
+
+    fn release(&self, bytes: usize) {
+        self.used.fetch_sub(bytes, AtomicOrdering::SeqCst);
+    }
+
+    fn new_run_path(&self, worker_index: usize) -> Result<PathBuf, FError> {
+        std::fs::create_dir_all(&self.dir)?;
+        self.runs.fetch_add(1, AtomicOrdering::SeqCst);
+        let seq = NEXT_RUN.fetch_add(1, AtomicOrdering::SeqCst);
+        let name = format!("sandflow-{}-{}-{}-{}.run", std::process::id(), self.job_id, worker_index, seq);
+        Ok(self.dir.join(name))
+    }
+}
+
+/// Memory reserved by an operator from the budget, it's released once dropped;
+struct Reserved {
+    budget: Option<Arc<MemoryBudget>>,
+    bytes: usize,
+}
+
+impl Reserved {
+    fn new(budget: Option<Arc<MemoryBudget>>) -> Self {
+        Self { budget, bytes: 0 }
+    }
+
+    /// Reserve `bytes` more, return false if the budget is exceeded, always succeed without budget;
+    fn grow(&mut self, bytes: usize) -> bool {
+        match self.budget.as_ref() {
+            Some(budget) if !budget.try_reserve(bytes) => false,
+            _ => {
+                self.bytes += bytes;
+                true
+            }
+        }
+    }
+
+    /// Reserve `bytes` more even if the budget is exceeded, it's used after the state is spilled, so an item larger
+    /// than the whole budget can still be processed;
+    fn force(&mut self, bytes: usize) {
+        if let Some(budget) = self.budget.as_ref() {
+            budget.force_reserve(bytes);
+        }
+        self.bytes += bytes;
+    }
+
+    /// Release `bytes` if the reserved state is shrunk;
+    fn shrink(&mut self, bytes: usize) {
+        let bytes = bytes.min(self.bytes);
+        if let Some(budget) = self.budget.as_ref() {
+            budget.release(bytes);
+        }
+        self.bytes -= bytes;
+    }
+
+    fn clear(&mut self) {
+        if let Some(budget) = self.budget.as_ref() {
+            budget.release(self.bytes);
+        }
+        self.bytes = 0;
+    }
+}
+
+impl Drop for Reserved {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+/// Take the accumulator out of an entry of `SpillGroupByKey`, it's only `None` while it's folded;
+#[inline]
+fn take_acc<K, V>((key, acc): (K, Option<V>)) -> (K, V) {
+    (key, acc.expect("accumulator lost;"))
+}
+
+/// Sort entries by key and write them as a new run, runs are merged into one once there are too many of them, so
+/// at most `MAX_OPEN_RUNS` files are kept open;
+fn spill_run<K, V>(
+    budget: &MemoryBudget, worker_index: usize, runs: &mut Vec<RunReader<K, V>>, mut entries: Vec<(K, V)>,
+) -> Result<(), FError>
+where
+    K: Ord + Spill,
+    V: Spill,
+{
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+    runs.push(write_run(budget, worker_index, entries.into_iter().map(Ok))?);
+    if runs.len() >= MAX_OPEN_RUNS {
+        let merged = SortedMerge::new(std::mem::take(runs), vec![], Reserved::new(None));
+        runs.push(write_run(budget, worker_index, merged)?);
+    }
+    Ok(())
+}
+
+/// Write entries sorted by key to a new run file, and open it to read back;
+fn write_run<K, V, I>(budget: &MemoryBudget, worker_index: usize, entries: I) -> Result<RunReader<K, V>, FError>
+where
+    K: Spill,
+    V: Spill,
+    I: IntoIterator<Item = Result<(K, V), FError>>,
+{
+    let path = budget.new_run_path(worker_index)?;
+    let mut writer = BufWriter::new(File::create(&path)?);
+    let mut buf = Vec::new();
+    let mut count = 0;
+    for entry in entries {
+        let (key, value) = entry?;
+        buf.clear();
+        key.encode(&mut buf);
+        value.encode(&mut buf);
+        writer.write_all(&(buf.len() as u64).to_le_bytes())?;
+        writer.write_all(&buf)?;
+        count += 1;
+    }
+    writer.flush()?;
+    drop(writer);
+    debug!("worker[{}]: spill {} entries to {};", worker_index, count, path.display());
+    let reader = BufReader::new(File::open(&path)?);
+    Ok(RunReader { path, reader, buf, _ph: PhantomData })
+}
+
+/// Read back entries of a run file, the file is removed once dropped;
+struct RunReader<K, V> {
+    path: PathBuf,
+    reader: BufReader<File>,
+    buf: Vec<u8>,
+    _ph: PhantomData<fn() -> (K, V)>,
+}
+
+impl<K: Spill, V: Spill> RunReader<K, V> {
+    fn next_entry(&mut self) -> Result<Option<(K, V)>, FError> {
+        let mut len = [0u8; 8];
+        match self.reader.read_exact(&mut len) {
+            Ok(()) => (),
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+        self.buf.resize(u64::from_le_bytes(len) as usize, 0);
+        self.reader.read_exact(&mut self.buf)?;
+        let mut buf = self.buf.as_slice();
+        let key = K::decode(&mut buf)?;
+        let value = V::decode(&mut buf)?;
+        Ok(Some((key, value)))
+    }
+}
+
+impl<K, V> Drop for RunReader<K, V> {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_file(&self.path) {
+            warn!("remove spilled run {} fail: {};", self.path.display(), e);
+        }
+    }
+}
+
+struct Head<K, V> {
+    key: K,
+    source: usize,
+    value: V,
+}
+
+impl<K: Ord, V> PartialEq for Head<K, V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<K: Ord, V> Eq for Head<K, V> {}
+
+impl<K: Ord, V> PartialOrd for Head<K, V> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<K: Ord, V> Ord for Head<K, V> {
+    // reversed, so the max-heap pops the smallest key, and entries of earlier sources come first among equal keys;
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.key.cmp(&self.key).then(other.source.cmp(&self.source))
+    }
+}
+
+/// Merge spilled runs and the sorted entries in memory into one sorted sequence, entries of equal keys keep the
+/// order they were buffered;
+pub struct SortedMerge<K, V> {
+    runs: Vec<RunReader<K, V>>,
+    memory: std::vec::IntoIter<(K, V)>,
+    heap: BinaryHeap<Head<K, V>>,
+    is_started: bool,
+    _reserved: Reserved,
+}
+
+impl<K: Ord + Spill, V: Spill> SortedMerge<K, V> {
+    fn new(runs: Vec<RunReader<K, V>>, memory: Vec<(K, V)>, reserved: Reserved) -> Self {
+        let heap = BinaryHeap::with_capacity(runs.len() + 1);
+        Self { runs, memory: memory.into_iter(), heap, is_started: false, _reserved: reserved }
+    }
+
+    fn fill(&mut self, source: usize) -> Result<(), FError> {
+        let entry = match self.runs.get_mut(source) {
+            Some(run) => run.next_entry()?,
+            None => self.memory.next(),
+        };
+        if let Some((key, value)) = entry {
+            self.heap.push(Head { key, source, value });
+        }
+        Ok(())
+    }
+}
+
+impl<K: Ord + Spill, V: Spill> Iterator for SortedMerge<K, V> {
+    type Item = Result<(K, V), FError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if !self.is_started {
+            self.is_started = true;
+            for source in 0..=self.runs.len() {
+                if let Err(e) = self.fill(source) {
+                    return Some(Err(e));
+                }
+            }
+        }
+        let Head { key, source, value } = self.heap.pop()?;
+        if let Err(e) = self.fill(source) {
+            return Some(Err(e));
+        }
+        Some(Ok((key, value)))
+    }
+}
+
+/// Buffer of `(key, item)` pairs which are read back sorted by key;
+pub trait RunBuffer<K, T> {
+    type Sorted: Iterator<Item = Result<(K, T), FError>>;
+
+    fn push(&mut self, key: K, item: T) -> Result<(), FError>;
+
+    fn into_sorted(self) -> Result<Self::Sorted, FError>;
+}
+
+/// Sorted entries all kept in memory;
+pub struct MemoryRun<K, T>(std::vec::IntoIter<(K, T)>);
+
+impl<K, T> Iterator for MemoryRun<K, T> {
+    type Item = Result<(K, T), FError>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(Ok)
+    }
+}
+
+impl<K: Ord, T> RunBuffer<K, T> for Vec<(K, T)> {
+    type Sorted = MemoryRun<K, T>;
+
+    #[inline]
+    fn push(&mut self, key: K, item: T) -> Result<(), FError> {
+        Vec::push(self, (key, item));
+        Ok(())
+    }
+
+    fn into_sorted(mut self) -> Result<Self::Sorted, FError> {
+        self.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(MemoryRun(self.into_iter()))
+    }
+}
+
+/// Buffer which writes its entries to disk as a sorted run each time the memory budget is exceeded, the run is
+/// written by the thread which pushes, so it blocks the executor thread until the file is written;
+pub struct SpillBuffer<K, T> {
+    worker_index: usize,
+    buffer: Vec<(K, T)>,
+    reserved: Reserved,
+    runs: Vec<RunReader<K, T>>,
+}
+
+impl<K, T> SpillBuffer<K, T> {
+    pub fn new(budget: Option<Arc<MemoryBudget>>, worker_index: usize) -> Self {
+        Self { worker_index, buffer: vec![], reserved: Reserved::new(budget), runs: vec![] }
+    }
+}
+
+impl<K: Ord + Spill, T: Spill> SpillBuffer<K, T> {
+    fn spill(&mut self) -> Result<(), FError> {
+        let budget = self.reserved.budget.as_ref().expect("spill without budget;");
+        let buffer = std::mem::take(&mut self.buffer);
+        spill_run(budget, self.worker_index, &mut self.runs, buffer)?;
+        self.reserved.clear();
+        Ok(())
+    }
+}
+
+impl<K: Ord + Spill, T: Spill> RunBuffer<K, T> for SpillBuffer<K, T> {
+    type Sorted = SortedMerge<K, T>;
+
+    fn push(&mut self, key: K, item: T) -> Result<(), FError> {
+        let size = key.mem_size() + item.mem_size();
+        if !self.reserved.grow(size) {
+            if !self.buffer.is_empty() {
+                self.spill()?;
+            }
+            self.reserved.force(size);
+        }
+        self.buffer.push((key, item));
+        Ok(())
+    }
+
+    fn into_sorted(mut self) -> Result<Self::Sorted, FError> {
+        let mut buffer = std::mem::take(&mut self.buffer);
+        buffer.sort_by(|a, b| a.0.cmp(&b.0));
+        let runs = std::mem::take(&mut self.runs);
+        let reserved = std::mem::replace(&mut self.reserved, Reserved::new(None));
+        Ok(SortedMerge::new(runs, buffer, reserved))
+    }
+}
+
+enum GroupOutput<K, V> {
+    Memory(hash_map::IntoIter<K, Option<V>>),
+    Merged(SortedMerge<K, V>, Option<(K, V)>),
+}
+
+/// Same as `GroupByKey`, but accumulators are written to disk as a run sorted by key each time the memory budget is
+/// exceeded, runs are merged after the upstream is exhausted, and accumulators of the same key are merged by `agg`;
+///
+/// Runs are written and read back with blocking file I/O inside `poll_next`, so the executor thread polling it is
+/// blocked while a run is written;
+pub struct SpillGroupByKey<K, T, V, A> {
+    input: StageInput<(K, T)>,
+    agg: A,
+    worker_index: usize,
+    // an accumulator is taken out of its entry while it's folded;
+    table: HashMap<K, Option<V>>,
+    reserved: Reserved,
+    runs: Vec<RunReader<K, V>>,
+    output: Option<GroupOutput<K, V>>,
+}
+
+impl<K, T, V, A> SpillGroupByKey<K, T, V, A> {
+    pub fn new(input: StageInput<(K, T)>, agg: A, budget: Option<Arc<MemoryBudget>>, worker_index: usize) -> Self {
+        Self { input, agg, worker_index, table: HashMap::new(), reserved: Reserved::new(budget), runs: vec![], output: None }
+    }
+}
+
+impl<K, T, V, A> SpillGroupByKey<K, T, V, A>
+where
+    K: Hash + Ord + Spill,
+    V: Spill,
+    A: KeyedFold<T, V> + KeyedMerge<V>,
+{
+    fn spill(&mut self) -> Result<(), FError> {
+        let budget = self.reserved.budget.as_ref().expect("spill without budget;");
+        let entries = self.table.drain().map(take_acc).collect::<Vec<_>>();
+        spill_run(budget, self.worker_index, &mut self.runs, entries)?;
+        self.reserved.clear();
+        Ok(())
+    }
+
+    fn next_output(&mut self) -> Option<Result<(K, V), FError>> {
+        match self.output.as_mut()? {
+            GroupOutput::Memory(iter) => iter.next().map(|entry| Ok(take_acc(entry))),
+            GroupOutput::Merged(merge, pending) => loop {
+                match merge.next() {
+                    Some(Ok((key, value))) => match pending.take() {
+                        Some((pk, pv)) if pk == key => *pending = Some((pk, self.agg.merge(pv, value))),
+                        Some(prev) => {
+                            *pending = Some((key, value));
+                            return Some(Ok(prev));
+                        }
+                        None => *pending = Some((key, value)),
+                    },
+                    Some(Err(e)) => return Some(Err(e)),
+                    None => return pending.take().map(Ok),
+                }
+            },
+        }
+    }
+}
+
+impl<K, T, V, A> Unpin for SpillGroupByKey<K, T, V, A> {}
+
+impl<K, T, V, A> Stream for SpillGroupByKey<K, T, V, A>
+where
+    K: Hash + Ord + Spill,
+    V: Spill,
+    A: KeyedFold<T, V> + KeyedMerge<V>,
+{
+    type Item = Result<(K, V), FError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if this.output.is_none() {
+            while let Some((key, item)) = ready!(Pin::new(&mut this.input).poll_next(cx)) {
+                match this.table.entry(key) {
+                    Entry::Occupied(mut entry) => {
+                        let acc = entry.get_mut().take().expect("accumulator lost;");
+                        let before = acc.mem_size();
+                        let acc = this.agg.next(acc, item);
+                        let after = acc.mem_size();
+                        entry.insert(Some(acc));
+                        if after < before {
+                            this.reserved.shrink(before - after);
+                        } else if after > before && !this.reserved.grow(after - before) {
+                            // the grown accumulator is spilled with others, it starts over on the next item of its key;
+                            this.spill()?;
+                        }
+                    }
+                    Entry::Vacant(entry) => {
+                        let acc = this.agg.first(item);
+                        let size = entry.key().mem_size() + acc.mem_size();
+                        if this.reserved.grow(size) {
+                            entry.insert(Some(acc));
+                        } else {
+                            let key = entry.into_key();
+                            if !this.table.is_empty() {
+                                this.spill()?;
+                            }
+                            this.reserved.force(size);
+                            this.table.insert(key, Some(acc));
+                        }
+                    }
+                }
+            }
+            let table = std::mem::take(&mut this.table);
+            if this.runs.is_empty() {
+                this.output = Some(GroupOutput::Memory(table.into_iter()));
+            } else {
+                let mut entries = table.into_iter().map(take_acc).collect::<Vec<_>>();
+                entries.sort_by(|a, b| a.0.cmp(&b.0));
+                let runs = std::mem::take(&mut this.runs);
+                let reserved = std::mem::replace(&mut this.reserved, Reserved::new(None));
+                this.output = Some(GroupOutput::Merged(SortedMerge::new(runs, entries, reserved), None));
+            }
+        }
+        Poll::Ready(this.next_output())
+    }
+}
@@ -0,0 +1,340 @@
+use std::any::Any;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::hash::Hash;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures::{ready, Stream};
+
+use crate::errors::FError;
+use crate::stages::source::StageInput;
+use crate::streams::event_time::{MergeWatermarks, Timed};
+
+/// States of all keys owned by a worker, each state is created by its first use and identified by its name;
+pub struct StateStore<K> {
+    states: HashMap<&'static str, Box<dyn Any + Send>>,
+    _ph: std::marker::PhantomData<K>,
+}
+
+impl<K: 'static> StateStore<K> {
+    pub fn new() -> Self {
+        Self { states: HashMap::new(), _ph: std::marker::PhantomData }
+    }
+
+    fn get_or_create<S: Default + Send + 'static>(&mut self, name: &'static str) -> &mut S {
+        self.states
+            .entry(name)
+            .or_insert_with(|| Box::new(S::default()))
+            .downcast_mut::<S>()
+            .unwrap_or_else(|| panic!("state '{}' is used with different types;", name))
+    }
+}
+
+impl<K: 'static> Default for StateStore<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Event-time timers of all keys owned by a worker, a timer is due once the watermark reaches its time;
+pub struct EventTimers<K> {
+    watermark: u64,
+    timers: BTreeMap<u64, HashSet<K>>,
+    due: VecDeque<(u64, K)>,
+}
+
+impl<K: Hash + Eq> EventTimers<K> {
+    pub fn new() -> Self {
+        Self { watermark: 0, timers: BTreeMap::new(), due: VecDeque::new() }
+    }
+
+    fn advance(&mut self, watermark: u64) {
+        self.watermark = self.watermark.max(watermark);
+    }
+
+    /// Take the next due timer, timers are due in order of time, and timers of the same time in no specific order;
+    fn next_due(&mut self) -> Option<(u64, K)> {
+        if self.due.is_empty() {
+            let entry = self.timers.first_entry()?;
+            if *entry.key() > self.watermark {
+                return None;
+            }
+            let ts = *entry.key();
+            self.due.extend(entry.remove().into_iter().map(|key| (ts, key)));
+        }
+        self.due.pop_front()
+    }
+}
+
+impl<K: Hash + Eq> Default for EventTimers<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Context of the item being processed, states got from it belong to the key of the item;
+pub struct KeyContext<'a, K, U> {
+    key: &'a K,
+    store: &'a mut StateStore<K>,
+    output: &'a mut VecDeque<U>,
+    timers: Option<&'a mut EventTimers<K>>,
+}
+
+impl<'a, K, U> KeyContext<'a, K, U>
+where
+    K: Hash + Eq + Clone + Send + 'static,
+{
+    pub fn key(&self) -> &K {
+        self.key
+    }
+
+    /// Emit an output item;
+    pub fn emit(&mut self, item: U) {
+        self.output.push_back(item);
+    }
+
+    /// The event-time watermark of the worker, only available in `process_timed`;
+    pub fn current_watermark(&self) -> u64 {
+        self.timers
+            .as_ref()
+            .expect("event time is only available in `process_timed`;")
+            .watermark
+    }
+
+    /// Register a timer of the current key which fires once the watermark reaches `ts`, registering a time twice
+    /// fires it once, and a time already reached fires right after the current call; only available in
+    /// `process_timed`;
+    pub fn register_event_timer(&mut self, ts: u64) {
+        self.timers
+            .as_mut()
+            .expect("event timers are only available in `process_timed`;")
+            .timers
+            .entry(ts)
+            .or_default()
+            .insert(self.key.clone());
+    }
+
+    /// Delete a timer of the current key which isn't fired yet;
+    pub fn delete_event_timer(&mut self, ts: u64) {
+        let timers = self
+            .timers
+            .as_mut()
+            .expect("event timers are only available in `process_timed`;");
+        if let Some(keys) = timers.timers.get_mut(&ts) {
+            keys.remove(self.key);
+            if keys.is_empty() {
+                timers.timers.remove(&ts);
+            }
+        }
+    }
+
+    pub fn value<V: Send + 'static>(&mut self, name: &'static str) -> ValueState<'_, K, V> {
+        ValueState { key: self.key, values: self.store.get_or_create(name) }
+    }
+
+    pub fn list<V: Send + 'static>(&mut self, name: &'static str) -> ListState<'_, K, V> {
+        ListState { key: self.key, lists: self.store.get_or_create(name) }
+    }
+
+    pub fn map<MK, MV>(&mut self, name: &'static str) -> MapState<'_, K, MK, MV>
+    where
+        MK: Hash + Eq + Send + 'static,
+        MV: Send + 'static,
+    {
+        MapState { key: self.key, maps: self.store.get_or_create(name) }
+    }
+}
+
+/// A value of the current key;
+pub struct ValueState<'a, K, V> {
+    key: &'a K,
+    values: &'a mut HashMap<K, V>,
+}
+
+impl<'a, K: Hash + Eq + Clone, V> ValueState<'a, K, V> {
+    pub fn get(&self) -> Option<&V> {
+        self.values.get(self.key)
+    }
+
+    pub fn set(&mut self, value: V) {
+        self.values.insert(self.key.clone(), value);
+    }
+
+    /// Update the value by `f`, which gets `None` if the value isn't set, the value is cleared if `f` returns `None`;
+    pub fn update<F: FnOnce(Option<V>) -> Option<V>>(&mut self, f: F) {
+        if let Some(value) = f(self.values.remove(self.key)) {
+            self.set(value);
+        }
+    }
+
+    pub fn clear(&mut self) -> Option<V> {
+        self.values.remove(self.key)
+    }
+}
+
+/// A list of values of the current key;
+pub struct ListState<'a, K, V> {
+    key: &'a K,
+    lists: &'a mut HashMap<K, Vec<V>>,
+}
+
+impl<'a, K: Hash + Eq + Clone, V> ListState<'a, K, V> {
+    pub fn push(&mut self, value: V) {
+        match self.lists.get_mut(self.key) {
+            Some(list) => list.push(value),
+            None => {
+                self.lists.insert(self.key.clone(), vec![value]);
+            }
+        }
+    }
+
+    pub fn get(&self) -> &[V] {
+        self.lists.get(self.key).map_or(&[], |list| list.as_slice())
+    }
+
+    pub fn len(&self) -> usize {
+        self.get().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.get().is_empty()
+    }
+
+    /// Take all values out, the list is cleared;
+    pub fn take(&mut self) -> Vec<V> {
+        self.lists.remove(self.key).unwrap_or_default()
+    }
+}
+
+/// A map of the current key;
+pub struct MapState<'a, K, MK, MV> {
+    key: &'a K,
+    maps: &'a mut HashMap<K, HashMap<MK, MV>>,
+}
+
+impl<'a, K: Hash + Eq + Clone, MK: Hash + Eq, MV> MapState<'a, K, MK, MV> {
+    pub fn get(&self, key: &MK) -> Option<&MV> {
+        self.maps.get(self.key).and_then(|map| map.get(key))
+    }
+
+    pub fn insert(&mut self, key: MK, value: MV) -> Option<MV> {
+        match self.maps.get_mut(self.key) {
+            Some(map) => map.insert(key, value),
+            None => {
+                self.maps
+                    .insert(self.key.clone(), HashMap::from([(key, value)]));
+                None
+            }
+        }
+    }
+
+    pub fn remove(&mut self, key: &MK) -> Option<MV> {
+        let map = self.maps.get_mut(self.key)?;
+        let value = map.remove(key);
+        if map.is_empty() {
+            self.maps.remove(self.key);
+        }
+        value
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&MK, &MV)> {
+        self.maps.get(self.key).into_iter().flatten()
+    }
+
+    pub fn clear(&mut self) {
+        self.maps.remove(self.key);
+    }
+}
+
+/// Call `f` with the context of its key for each item, items of a key are always processed by the same worker;
+pub struct Process<K, T, U, F> {
+    input: StageInput<(K, T)>,
+    f: F,
+    store: StateStore<K>,
+    output: VecDeque<U>,
+}
+
+impl<K: 'static, T, U, F> Process<K, T, U, F> {
+    pub fn new(input: StageInput<(K, T)>, f: F) -> Self {
+        Self { input, f, store: StateStore::new(), output: VecDeque::new() }
+    }
+}
+
+impl<K, T, U, F> Unpin for Process<K, T, U, F> {}
+
+impl<K, T, U, F> Stream for Process<K, T, U, F>
+where
+    K: Hash + Eq + Clone + Send + 'static,
+    F: FnMut(&mut KeyContext<'_, K, U>, T) -> Result<(), FError>,
+{
+    type Item = Result<U, FError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            if let Some(output) = this.output.pop_front() {
+                return Poll::Ready(Some(Ok(output)));
+            }
+            match ready!(Pin::new(&mut this.input).poll_next(cx)) {
+                Some((key, item)) => {
+                    let mut ctx = KeyContext { key: &key, store: &mut this.store, output: &mut this.output, timers: None };
+                    (this.f)(&mut ctx, item)?;
+                }
+                None => return Poll::Ready(None),
+            }
+        }
+    }
+}
+
+/// Same as `Process`, but items carry their event time, and timers registered by the context fire `on_timer` once
+/// the watermark reaches them;
+pub struct TimedProcess<K, T, U, F, OT> {
+    input: MergeWatermarks<(K, T)>,
+    on_item: F,
+    on_timer: OT,
+    store: StateStore<K>,
+    timers: EventTimers<K>,
+    output: VecDeque<U>,
+}
+
+impl<K: Hash + Eq + 'static, T, U, F, OT> TimedProcess<K, T, U, F, OT> {
+    pub fn new(input: MergeWatermarks<(K, T)>, on_item: F, on_timer: OT) -> Self {
+        Self { input, on_item, on_timer, store: StateStore::new(), timers: EventTimers::new(), output: VecDeque::new() }
+    }
+}
+
+impl<K, T, U, F, OT> Unpin for TimedProcess<K, T, U, F, OT> {}
+
+impl<K, T, U, F, OT> Stream for TimedProcess<K, T, U, F, OT>
+where
+    K: Hash + Eq + Clone + Send + 'static,
+    F: FnMut(&mut KeyContext<'_, K, U>, u64, T) -> Result<(), FError>,
+    OT: FnMut(&mut KeyContext<'_, K, U>, u64) -> Result<(), FError>,
+{
+    type Item = Result<U, FError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            if let Some(output) = this.output.pop_front() {
+                return Poll::Ready(Some(Ok(output)));
+            }
+            if let Some((ts, key)) = this.timers.next_due() {
+                let timers = Some(&mut this.timers);
+                let mut ctx = KeyContext { key: &key, store: &mut this.store, output: &mut this.output, timers };
+                (this.on_timer)(&mut ctx, ts)?;
+                continue;
+            }
+            match ready!(Pin::new(&mut this.input).poll_next(cx)) {
+                Some(Ok(Timed::Item(ts, (key, item)))) => {
+                    let timers = Some(&mut this.timers);
+                    let mut ctx = KeyContext { key: &key, store: &mut this.store, output: &mut this.output, timers };
+                    (this.on_item)(&mut ctx, ts, item)?;
+                }
+                Some(Ok(Timed::Watermark(watermark))) => this.timers.advance(watermark),
+                Some(Err(e)) => return Poll::Ready(Some(Err(e))),
+                None => return Poll::Ready(None),
+            }
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures::{ready, Stream};
+use pin_project_lite::pin_project;
+
+use crate::errors::FError;
+use crate::streams::aggregate::Aggregate;
+
+pub type TopK<K, T> =
+    IntoSorted<Aggregate<BoundedHeap<K, T>, fn(BoundedHeap<K, T>, BoundedHeap<K, T>) -> BoundedHeap<K, T>>, T>;
+
+struct Entry<K, T> {
+    key: K,
+    item: T,
+}
+
+impl<K: Ord, T> PartialEq for Entry<K, T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key
+    }
+}
+
+impl<K: Ord, T> Eq for Entry<K, T> {}
+
+impl<K: Ord, T> PartialOrd for Entry<K, T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<K: Ord, T> Ord for Entry<K, T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key.cmp(&other.key)
+    }
+}
+
+/// Keep at most `k` items with the largest keys;
+pub struct BoundedHeap<K, T> {
+    k: usize,
+    // min-heap, so the smallest kept key is on the top;
+    heap: BinaryHeap<Reverse<Entry<K, T>>>,
+}
+
+impl<K: Ord, T> BoundedHeap<K, T> {
+    pub fn new(k: usize) -> Self {
+        Self { k, heap: BinaryHeap::with_capacity(k) }
+    }
+
+    pub fn push(&mut self, key: K, item: T) {
+        if self.heap.len() < self.k {
+            self.heap.push(Reverse(Entry { key, item }));
+        } else if let Some(mut min) = self.heap.peek_mut() {
+            if min.0.key < key {
+                *min = Reverse(Entry { key, item });
+            }
+        }
+    }
+
+    pub fn merge(mut self, other: Self) -> Self {
+        for Reverse(entry) in other.heap {
+            self.push(entry.key, entry.item);
+        }
+        self
+    }
+
+    /// Items in descending order of keys;
+    pub fn into_sorted(self) -> Vec<T> {
+        self.heap
+            .into_sorted_vec()
+            .into_iter()
+            .map(|Reverse(e)| e.item)
+            .collect()
+    }
+}
+
+pin_project! {
+    /// Emit items of each heap of the upstream in descending order of keys;
+    pub struct IntoSorted<St, T> {
+        #[pin]
+        stream: St,
+        output: Option<std::vec::IntoIter<T>>,
+    }
+}
+
+impl<St, T> IntoSorted<St, T> {
+    pub fn new(stream: St) -> Self {
+        Self { stream, output: None }
+    }
+}
+
+impl<St, K, T> Stream for IntoSorted<St, T>
+where
+    St: Stream<Item = Result<BoundedHeap<K, T>, FError>>,
+    K: Ord,
+{
+    type Item = Result<T, FError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        loop {
+            if let Some(item) = this.output.as_mut().and_then(|output| output.next()) {
+                return Poll::Ready(Some(Ok(item)));
+            }
+            match ready!(this.stream.as_mut().poll_next(cx)) {
+                Some(heap) => *this.output = Some(heap?.into_sorted().into_iter()),
+                None => return Poll::Ready(None),
+            }
+        }
+    }
+}
@@ -0,0 +1,281 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::hash::Hash;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use futures::future::BoxFuture;
+use futures::stream::Map;
+use futures::Stream;
+use pin_project_lite::pin_project;
+use sandflow_executor::Spawner;
+
+use crate::errors::FError;
+use crate::stages::source::StageInput;
+
+pub type KeyedWindow<K, T> = Windowed<Map<StageInput<(K, T)>, fn((K, T)) -> Result<(K, T), FError>>, K, T>;
+
+pub type LocalWindow<St, T> = Windowed<Map<St, fn(Result<T, FError>) -> Result<((), T), FError>>, (), T>;
+
+/// How items are grouped into windows by their timestamps;
+#[derive(Clone, Copy, Debug)]
+pub enum Window {
+    /// Fixed size windows which don't overlap;
+    Tumbling(Duration),
+    /// Windows of `size` which start every `slide`, an item may belong to more than one window;
+    Sliding(Duration, Duration),
+    /// A window of a key is closed if no item of the key arrives in the `gap`;
+    Session(Duration),
+}
+
+/// Window of time in `[start, end)`, in milliseconds since the unix epoch;
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TimeWindow {
+    pub start: u64,
+    pub end: u64,
+}
+
+/// Items of a key in a window;
+#[derive(Debug)]
+pub struct WindowResult<K, T> {
+    pub key: K,
+    pub window: TimeWindow,
+    pub items: Vec<T>,
+}
+
+#[inline]
+pub(crate) fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+struct Pane<T> {
+    window: TimeWindow,
+    items: Vec<T>,
+}
+
+/// Windows of keys which are not fired yet;
+pub(crate) struct WindowState<K, T> {
+    window: Window,
+    panes: HashMap<K, Vec<Pane<T>>>,
+    // keys with windows to fire at the time, entries are not removed if a session is extended, they are skipped
+    // once fired;
+    deadlines: BTreeMap<u64, Vec<K>>,
+}
+
+impl<K, T> WindowState<K, T>
+where
+    K: Hash + Eq + Clone,
+    T: Clone,
+{
+    pub fn new(window: Window) -> Self {
+        let durations = match window {
+            Window::Tumbling(size) | Window::Session(size) => vec![size],
+            Window::Sliding(size, slide) => vec![size, slide],
+        };
+        assert!(durations.iter().all(|d| d.as_millis() > 0), "window durations should be at least 1ms");
+        Self { window, panes: HashMap::new(), deadlines: BTreeMap::new() }
+    }
+
+    /// Add the item with timestamp `ts` to windows it belongs to;
+    pub fn assign(&mut self, key: K, ts: u64, item: T) {
+        let panes = self.panes.entry(key.clone()).or_default();
+        match self.window {
+            Window::Tumbling(size) => {
+                let size = size.as_millis() as u64;
+                let start = ts - ts % size;
+                Self::add(panes, &mut self.deadlines, &key, TimeWindow { start, end: start + size }, item);
+            }
+            Window::Sliding(size, slide) => {
+                let (size, slide) = (size.as_millis() as u64, slide.as_millis() as u64);
+                let mut start = ts - ts % slide;
+                let mut windows = vec![];
+                while start + size > ts {
+                    windows.push(TimeWindow { start, end: start + size });
+                    match start.checked_sub(slide) {
+                        Some(s) => start = s,
+                        None => break,
+                    }
+                }
+                if let Some((last, others)) = windows.split_last() {
+                    for w in others {
+                        Self::add(panes, &mut self.deadlines, &key, *w, item.clone());
+                    }
+                    Self::add(panes, &mut self.deadlines, &key, *last, item);
+                }
+            }
+            Window::Session(gap) => {
+                // sessions overlapping with the new one are merged into it, as items may arrive out of order;
+                let mut window = TimeWindow { start: ts, end: ts + gap.as_millis() as u64 };
+                let mut items = vec![];
+                let mut ends = vec![];
+                let mut i = 0;
+                while i < panes.len() {
+                    if panes[i].window.start < window.end && window.start < panes[i].window.end {
+                        let pane = panes.swap_remove(i);
+                        window.start = window.start.min(pane.window.start);
+                        window.end = window.end.max(pane.window.end);
+                        ends.push(pane.window.end);
+                        items.extend(pane.items);
+                    } else {
+                        i += 1;
+                    }
+                }
+                items.push(item);
+                panes.push(Pane { window, items });
+                if !ends.contains(&window.end) {
+                    self.deadlines.entry(window.end).or_default().push(key);
+                }
+            }
+        }
+    }
+
+    /// If all windows an item with timestamp `ts` belongs to are fired at `time`;
+    pub fn is_late(&self, ts: u64, time: u64) -> bool {
+        let last_end = match self.window {
+            Window::Tumbling(size) => {
+                let size = size.as_millis() as u64;
+                ts - ts % size + size
+            }
+            Window::Sliding(size, slide) => ts - ts % slide.as_millis() as u64 + size.as_millis() as u64,
+            Window::Session(gap) => ts + gap.as_millis() as u64,
+        };
+        last_end <= time
+    }
+
+    fn add(panes: &mut Vec<Pane<T>>, deadlines: &mut BTreeMap<u64, Vec<K>>, key: &K, window: TimeWindow, item: T) {
+        match panes.iter_mut().find(|p| p.window == window) {
+            Some(pane) => pane.items.push(item),
+            None => {
+                panes.push(Pane { window, items: vec![item] });
+                deadlines.entry(window.end).or_default().push(key.clone());
+            }
+        }
+    }
+
+    /// The earliest time a window may be fired;
+    pub fn next_deadline(&self) -> Option<u64> {
+        self.deadlines.keys().next().copied()
+    }
+
+    /// Fire windows which end no later than `time`;
+    pub fn fire(&mut self, time: u64, output: &mut VecDeque<WindowResult<K, T>>) {
+        while let Some(entry) = self.deadlines.first_entry() {
+            if *entry.key() > time {
+                break;
+            }
+            for key in entry.remove() {
+                if let Some(panes) = self.panes.get_mut(&key) {
+                    let mut i = 0;
+                    while i < panes.len() {
+                        if panes[i].window.end <= time {
+                            let pane = panes.swap_remove(i);
+                            output.push_back(WindowResult { key: key.clone(), window: pane.window, items: pane.items });
+                        } else {
+                            i += 1;
+                        }
+                    }
+                    if panes.is_empty() {
+                        self.panes.remove(&key);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Fire all windows left;
+    pub fn flush(&mut self, output: &mut VecDeque<WindowResult<K, T>>) {
+        self.deadlines.clear();
+        let mut results = vec![];
+        for (key, panes) in self.panes.drain() {
+            for pane in panes {
+                results.push(WindowResult { key: key.clone(), window: pane.window, items: pane.items });
+            }
+        }
+        results.sort_by_key(|r| r.window.end);
+        output.extend(results);
+    }
+}
+
+pin_project! {
+    /// Group items into windows by the time they arrive, windows are fired by timers of the spawner once they end,
+    /// and windows left are fired after the upstream is exhausted;
+    pub struct Windowed<St, K, T> {
+        #[pin]
+        stream: St,
+        state: WindowState<K, T>,
+        output: VecDeque<WindowResult<K, T>>,
+        spawner: Arc<dyn Spawner>,
+        timer: Option<(u64, BoxFuture<'static, ()>)>,
+        is_done: bool,
+    }
+}
+
+impl<St, K, T> Windowed<St, K, T>
+where
+    K: Hash + Eq + Clone,
+    T: Clone,
+{
+    pub fn new(stream: St, window: Window, spawner: Arc<dyn Spawner>) -> Self {
+        let state = WindowState::new(window);
+        Self { stream, state, output: VecDeque::new(), spawner, timer: None, is_done: false }
+    }
+}
+
+impl<St, K, T> Stream for Windowed<St, K, T>
+where
+    St: Stream<Item = Result<(K, T), FError>>,
+    K: Hash + Eq + Clone,
+    T: Clone,
+{
+    type Item = Result<WindowResult<K, T>, FError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        loop {
+            if let Some(result) = this.output.pop_front() {
+                return Poll::Ready(Some(Ok(result)));
+            }
+            if *this.is_done {
+                return Poll::Ready(None);
+            }
+
+            let now = now_millis();
+            this.state.fire(now, this.output);
+            if !this.output.is_empty() {
+                continue;
+            }
+
+            match this.stream.as_mut().poll_next(cx) {
+                Poll::Ready(Some(Ok((key, item)))) => {
+                    this.state.assign(key, now, item);
+                    continue;
+                }
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(e))),
+                Poll::Ready(None) => {
+                    *this.is_done = true;
+                    *this.timer = None;
+                    this.state.flush(this.output);
+                    continue;
+                }
+                Poll::Pending => (),
+            }
+
+            let deadline = match this.state.next_deadline() {
+                Some(deadline) => deadline,
+                None => return Poll::Pending,
+            };
+            if this.timer.as_ref().map_or(true, |(at, _)| *at != deadline) {
+                let delay = Duration::from_millis(deadline.saturating_sub(now));
+                *this.timer = Some((deadline, this.spawner.sleep(delay)));
+            }
+            let (_, timer) = this.timer.as_mut().expect("window timer lost;");
+            match timer.as_mut().poll(cx) {
+                Poll::Ready(()) => *this.timer = None,
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
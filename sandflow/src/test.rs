@@ -1 +1,1014 @@
+use futures::executor::block_on;
+use futures::StreamExt;
 
+use crate::errors::FError;
+use crate::spawn_job;
+
+fn source(range: std::ops::Range<u64>) -> impl futures::Stream<Item = Result<u64, FError>> + Send + Unpin + 'static {
+    futures::stream::iter(range).map(Ok)
+}
+
+fn collect<T>(results: crate::ResultStream<T>) -> Vec<T> {
+    block_on(results.map(|r| r.expect("job failed;")).collect())
+}
+
+#[test]
+fn iterate_until_converged() {
+    let results = spawn_job(1, 4, source(0..100), || {
+        |st| {
+            st.map(Ok).iterate(100, |body| {
+                let mut branches = body.map(Ok).exchange(|x| *x).map(|x| Ok(x + 1)).split(2);
+                let exit = branches.pop().unwrap().map(Ok).filter(|x| *x >= 10);
+                let feedback = branches.pop().unwrap().map(Ok).filter(|x| *x < 10);
+                (feedback, exit)
+            })
+        }
+    });
+    let mut results = collect(results);
+    results.sort();
+    let expected = (0..100u64).map(|x| x.max(9) + 1).collect::<Vec<_>>();
+    assert_eq!(results, expected);
+}
+
+#[test]
+fn iterate_stops_at_max_rounds() {
+    let results = spawn_job(2, 4, source(0..100), || {
+        |st| {
+            st.map(Ok).iterate(5, |body| {
+                let mut branches = body.map(Ok).exchange(|x| *x).map(|x| Ok(x + 1)).split(2);
+                let exit = branches.pop().unwrap().map(Ok);
+                let feedback = branches.pop().unwrap().map(Ok);
+                (feedback, exit)
+            })
+        }
+    });
+    let results = collect(results);
+    assert_eq!(results.len(), 500);
+    assert_eq!(results.iter().max(), Some(&104));
+}
+
+#[test]
+fn iterate_empty_input() {
+    let results = spawn_job(3, 4, source(0..0), || {
+        |st| {
+            st.map(Ok).iterate(10, |body| {
+                let mut branches = body.map(|x| Ok(x + 1)).split(2);
+                let exit = branches.pop().unwrap().map(Ok);
+                let feedback = branches.pop().unwrap().map(Ok);
+                (feedback, exit)
+            })
+        }
+    });
+    assert!(collect(results).is_empty());
+}
+
+#[test]
+fn only_first_error_is_returned() {
+    let results = spawn_job(4, 4, source(0..10000), || {
+        |st| st.map(|x| if x % 2 == 0 { Err(FError::StrHint(format!("fail on {}", x))) } else { Ok(x) })
+    });
+    let results = block_on(results.collect::<Vec<_>>());
+    let errors = results.iter().filter(|r| r.is_err()).count();
+    assert_eq!(errors, 1);
+    assert!(results.last().unwrap().is_err());
+}
+
+#[test]
+fn panic_error_display() {
+    let panic = FError::Panic { worker: Some(0), stage: Some(3), message: "boom".to_owned() };
+    let error = crate::JobError::new(1, Some(0), Some(3), Some("exchange"), panic);
+    assert_eq!(error.to_string(), "job(1) worker[0]: stage(3) of exchange panicked: boom");
+}
+
+/// Run `job` in another thread, and fail if it doesn't finish in time, e.g. it's deadlocked;
+fn with_timeout<T, F>(job: F) -> T
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    let (tx, rx) = std::sync::mpsc::channel();
+    std::thread::spawn(move || tx.send(job()));
+    rx.recv_timeout(std::time::Duration::from_secs(30))
+        .expect("job is not finished in time;")
+}
+
+#[test]
+fn self_join_of_split_branches() {
+    let mut results = with_timeout(|| {
+        let results = spawn_job(5, 2, source(0..10000), || {
+            |st| {
+                let mut branches = st.map(Ok).split(2);
+                let right = branches.pop().unwrap().map(Ok);
+                let left = branches.pop().unwrap().map(Ok);
+                left.join(right, |x| *x, |x| *x)
+            }
+        });
+        collect(results)
+    });
+    results.sort();
+    assert_eq!(results, (0..10000u64).map(|x| (x, x)).collect::<Vec<_>>());
+}
+
+#[test]
+fn growing_accumulators_are_spilled() {
+    use std::sync::Arc;
+
+    use crate::stages::source::StageInput;
+    use crate::streams::keyed::Reduce;
+    use crate::streams::spill::{MemoryBudget, SpillGroupByKey};
+
+    let (mut tx, rx) = futures::channel::mpsc::channel(1000);
+    for i in 0..1000u64 {
+        tx.try_send((i % 4, format!("{},", i))).unwrap();
+    }
+    drop(tx);
+
+    let budget = Arc::new(MemoryBudget::new(6, 512, std::env::temp_dir()));
+    let concat = Reduce::new(|mut acc: String, s: String| {
+        acc.push_str(&s);
+        acc
+    });
+    let group = SpillGroupByKey::new(StageInput::new(rx), concat, Some(budget.clone()), 0);
+    let mut results = block_on(group.map(|r| r.unwrap()).collect::<Vec<_>>());
+    results.sort();
+
+    assert!(budget.spilled_runs() > 0);
+    assert_eq!(budget.used(), 0);
+    let expected = (0..4u64)
+        .map(|k| {
+            (
+                k,
+                (k..1000)
+                    .step_by(4)
+                    .map(|i| format!("{},", i))
+                    .collect::<String>(),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(results, expected);
+}
+
+#[test]
+fn event_timers_fire_on_watermark() {
+    let results = spawn_job(7, 2, source(0..100), || {
+        |st| {
+            st.map(Ok)
+                .assign_timestamps(|x| *x * 10, std::time::Duration::ZERO)
+                .process_timed(
+                    |x| *x % 3,
+                    |ctx, ts, x| {
+                        let end = ts - ts % 200 + 199;
+                        let mut buckets = ctx.map::<u64, Vec<u64>>("buckets");
+                        let mut items = buckets.remove(&end).unwrap_or_default();
+                        items.push(x);
+                        buckets.insert(end, items);
+                        ctx.register_event_timer(end);
+                        Ok(())
+                    },
+                    |ctx, ts| {
+                        assert!(ctx.current_watermark() >= ts);
+                        let mut items = ctx.map::<u64, Vec<u64>>("buckets").remove(&ts).unwrap();
+                        items.sort();
+                        let key = *ctx.key();
+                        ctx.emit((key, ts, items));
+                        Ok(())
+                    },
+                )
+        }
+    });
+    let mut results = collect(results);
+    results.sort();
+    let mut expected = vec![];
+    for key in 0..3u64 {
+        for ts in (199..1000u64).step_by(200) {
+            let items = (0..100u64)
+                .filter(|x| x % 3 == key && x * 10 / 200 == ts / 200)
+                .collect::<Vec<_>>();
+            expected.push((key, ts, items));
+        }
+    }
+    assert_eq!(results, expected);
+}
+
+#[test]
+fn idle_worker_does_not_hold_back_watermark() {
+    let window = with_timeout(|| {
+        let config = crate::JobConfig::new(8, 2).idle_timeout(std::time::Duration::from_millis(100));
+        // items keep coming, so only the worker without items is idle;
+        let more = futures::stream::unfold(10u64, |x| async move {
+            sandflow_executor::delay(std::time::Duration::from_millis(10)).await;
+            Some((Ok(x), x + 1))
+        });
+        let source = source(0..10).chain(more).boxed();
+        let results = crate::spawn_job_with(config, source, || {
+            |st| {
+                let (windows, _late) = st
+                    .map(Ok)
+                    .filter(|_| crate::worker_index() == Some(0))
+                    .assign_timestamps(|x| *x * 1000, std::time::Duration::ZERO)
+                    .event_window_by_key(|_| 0u64, crate::Window::Tumbling(std::time::Duration::from_secs(2)));
+                windows
+            }
+        });
+        block_on(results.map(|r| r.expect("job failed;")).next())
+    });
+    // no window is fired if the watermark is held back by the worker without items;
+    assert!(window.expect("no window is fired;").window.end <= 10000);
+}
+
+#[test]
+fn aggregate_on_one_worker() {
+    let count = collect(spawn_job(9, 1, source(0..100), || |st| st.map(Ok).count()));
+    assert_eq!(count, vec![100]);
+    let sum = collect(spawn_job(10, 1, source(0..100), || |st| st.map(Ok).sum()));
+    assert_eq!(sum, vec![4950]);
+}
+
+#[test]
+fn aggregate_of_all_workers() {
+    let results = spawn_job(11, 4, source(0..100), || {
+        |st| {
+            st.map(Ok).aggregate(
+                vec![],
+                |mut acc: Vec<u64>, x| {
+                    acc.push(x);
+                    acc
+                },
+                |mut a, b| {
+                    a.extend(b);
+                    a
+                },
+            )
+        }
+    });
+    let mut results = collect(results);
+    assert_eq!(results.len(), 1);
+    results[0].sort();
+    assert_eq!(results[0], (0..100).collect::<Vec<_>>());
+}
+
+#[test]
+fn fold_and_reduce_by_key() {
+    for parallel in [1, 4] {
+        let results = spawn_job(12, parallel, source(0..100), || {
+            |st| {
+                st.map(Ok).fold_by_key(
+                    |x| *x % 3,
+                    Vec::new,
+                    |mut acc, x| {
+                        acc.push(x);
+                        acc
+                    },
+                )
+            }
+        });
+        let mut results = collect(results);
+        results.sort();
+        results.iter_mut().for_each(|(_, values)| values.sort());
+        let expected = (0..3u64)
+            .map(|k| (k, (0..100).filter(|x| x % 3 == k).collect::<Vec<_>>()))
+            .collect::<Vec<_>>();
+        assert_eq!(results, expected);
+
+        let results = spawn_job(13, parallel, source(0..100), || |st| st.map(Ok).reduce_by_key(|x| *x % 3, |a, b| a + b));
+        let mut results = collect(results);
+        results.sort();
+        let expected = (0..3u64)
+            .map(|k| (k, (0..100).filter(|x| x % 3 == k).sum()))
+            .collect::<Vec<_>>();
+        assert_eq!(results, expected);
+    }
+}
+
+#[test]
+fn broadcast_to_all_workers() {
+    for parallel in [1, 4] {
+        let mut results = collect(spawn_job(14, parallel, source(0..10), || |st| st.map(Ok).broadcast().map(Ok)));
+        results.sort();
+        let expected = (0..10u64)
+            .flat_map(|x| (0..parallel).map(move |_| x))
+            .collect::<Vec<_>>();
+        assert_eq!(results, expected);
+    }
+}
+
+#[test]
+fn join_on_one_worker() {
+    let results = crate::spawn_job2(15, 1, source(0..10), source(5..15), || {
+        |left, right| left.map(Ok).join(right.map(Ok), |x| *x, |x| *x)
+    });
+    let mut results = collect(results);
+    results.sort();
+    assert_eq!(results, (5..10u64).map(|x| (x, x)).collect::<Vec<_>>());
+
+    let results = crate::spawn_job2(16, 1, source(0..10), source(5..15), || {
+        |left, right| left.map(Ok).left_join(right.map(Ok), |x| *x, |x| *x)
+    });
+    let mut results = collect(results);
+    results.sort();
+    let expected = (0..10u64)
+        .map(|x| (x, Some(x).filter(|x| *x >= 5)))
+        .collect::<Vec<_>>();
+    assert_eq!(results, expected);
+}
+
+#[test]
+fn dropped_split_branch_aborts_job() {
+    let results = with_timeout(|| {
+        let results = spawn_job(17, 2, source(0..100), || {
+            |st| {
+                let mut branches = st.map(Ok).split(2);
+                drop(branches.pop());
+                branches.pop().unwrap().map(Ok)
+            }
+        });
+        block_on(results.collect::<Vec<_>>())
+    });
+    let error = results.last().unwrap().as_ref().unwrap_err();
+    assert!(matches!(error.error(), FError::ChSend(_)));
+    assert_eq!(error.operator(), Some("split"));
+}
+
+#[test]
+fn cancel_job_with_pending_source() {
+    let (handle, results) = with_timeout(|| {
+        let source = futures::stream::pending::<Result<u64, FError>>();
+        let results = spawn_job(18, 2, source, || |st| st.map(Ok));
+        let handle = results.job_handle();
+        handle.cancel();
+        block_on(handle.join());
+        (handle, block_on(results.collect::<Vec<_>>()))
+    });
+    assert!(handle.is_cancelled());
+    assert!(handle.is_finished());
+    assert!(results.is_empty());
+}
+
+#[test]
+fn join_finished_job() {
+    let results = spawn_job(19, 2, source(0..100), || |st| st.map(Ok));
+    let handle = results.job_handle();
+    assert_eq!(collect(results).len(), 100);
+    with_timeout(move || block_on(handle.join()));
+}
+
+#[test]
+fn dropping_results_cancels_job() {
+    let handle = with_timeout(|| {
+        let mut results = spawn_job(20, 2, source(0..u64::MAX), || |st| st.map(Ok));
+        let handle = results.job_handle();
+        assert!(block_on(results.next()).unwrap().is_ok());
+        drop(results);
+        block_on(handle.join());
+        handle
+    });
+    assert!(handle.is_cancelled());
+}
+
+#[test]
+fn job_on_configured_executor() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let started = Arc::new(AtomicUsize::new(0));
+    let counter = started.clone();
+    let executor = crate::SandFlowExecutor::new(
+        crate::ExecutorConfig::new()
+            .pool_size(2)
+            .name_prefix("configured-")
+            .after_start(move |_| {
+                counter.fetch_add(1, Ordering::SeqCst);
+            }),
+    );
+    let config = crate::JobConfig::new(21, 2).spawner(executor);
+    let results = crate::spawn_job_with(config, source(0..10), || {
+        |st| st.map(|x| Ok((x, std::thread::current().name().map(|name| name.to_owned()))))
+    });
+    let results = collect(results);
+    assert_eq!(results.len(), 10);
+    assert!(results.iter().all(|(_, name)| name
+        .as_deref()
+        .is_some_and(|name| name.starts_with("configured-"))));
+    // threads of the pool may still be starting after the job is finished;
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+    while started.load(Ordering::SeqCst) < 2 && std::time::Instant::now() < deadline {
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    assert_eq!(started.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn install_after_global_executor_is_created() {
+    assert_eq!(collect(spawn_job(22, 2, source(0..10), || |st| st.map(Ok))).len(), 10);
+    assert!(!crate::install(crate::ExecutorConfig::new().pool_size(1)));
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+fn job_on_tokio_runtime() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_time()
+        .build()
+        .unwrap();
+    let config = crate::JobConfig::new(23, 2).spawner(crate::TokioSpawner::new(runtime.handle().clone()));
+    let results = crate::spawn_job_with(config, source(0..10), || {
+        |st| {
+            // the first attempt of each item fails, so retries are timed by timers of the runtime;
+            st.map(|x| Ok((x, Arc::new(AtomicBool::new(false)))))
+                .then_retry(
+                    |(x, is_tried)| async move {
+                        if is_tried.swap(true, Ordering::SeqCst) {
+                            Ok(x)
+                        } else {
+                            Err(FError::StrHint("first attempt;".to_owned()))
+                        }
+                    },
+                    crate::RetryPolicy::new(2).initial_backoff(std::time::Duration::from_millis(10)),
+                )
+        }
+    });
+    let mut results = runtime.block_on(results.map(|r| r.expect("job failed;")).collect::<Vec<_>>());
+    results.sort();
+    assert_eq!(results, (0..10u64).collect::<Vec<_>>());
+}
+
+#[test]
+fn panic_in_stage_fails_job() {
+    let results = with_timeout(|| {
+        let results = spawn_job(24, 2, source(0..100), || {
+            |st| {
+                st.map(|x| {
+                    assert_ne!(x, 50, "map on 50");
+                    Ok(x)
+                })
+            }
+        });
+        block_on(results.collect::<Vec<_>>())
+    });
+    let error = results.last().unwrap().as_ref().unwrap_err();
+    assert!(error.worker_index().is_some());
+    match error.error() {
+        FError::Panic { message, .. } => assert!(message.contains("map on 50")),
+        e => panic!("unexpected error {}", e),
+    }
+}
+
+#[test]
+fn panic_in_source_fails_job() {
+    let results = with_timeout(|| {
+        let source = source(0..100).map(|x| {
+            assert_ne!(x.as_ref().ok(), Some(&50), "source on 50");
+            x
+        });
+        block_on(spawn_job(25, 2, source, || |st| st.map(Ok)).collect::<Vec<_>>())
+    });
+    let error = results.last().unwrap().as_ref().unwrap_err();
+    assert!(error.worker_index().is_none());
+    match error.error() {
+        FError::Panic { message, .. } => assert!(message.contains("source on 50")),
+        e => panic!("unexpected error {}", e),
+    }
+}
+
+#[test]
+fn collect_errors_of_all_workers() {
+    use std::sync::{Arc, Barrier};
+
+    let barrier = Arc::new(Barrier::new(4));
+    let config = crate::JobConfig::new(26, 4).collect_errors(10);
+    let results = crate::spawn_job_with(config, source(0..100), || {
+        let barrier = barrier.clone();
+        move |st| {
+            // all workers fail together, so no one is aborted before it fails;
+            st.map(move |x| {
+                barrier.wait();
+                Err::<u64, _>(FError::StrHint(format!("fail on {}", x)))
+            })
+        }
+    });
+    let results = with_timeout(move || block_on(results.collect::<Vec<_>>()));
+    assert_eq!(results.len(), 1);
+    let error = results.into_iter().next().unwrap().unwrap_err();
+    assert_eq!(error.suppressed().len(), 3);
+    let mut workers = std::iter::once(&error)
+        .chain(error.suppressed())
+        .map(|e| e.worker_index().unwrap())
+        .collect::<Vec<_>>();
+    workers.sort();
+    assert_eq!(workers, vec![0, 1, 2, 3]);
+    assert!(error.to_string().ends_with("(and 3 more errors)"));
+}
+
+fn fail_on_even(x: u64) -> Result<u64, FError> {
+    if x % 2 == 0 {
+        Err(FError::StrHint(format!("even {}", x)))
+    } else {
+        Ok(x)
+    }
+}
+
+#[test]
+fn skip_errors() {
+    let results = spawn_job(27, 4, source(0..100), || |st| st.map(fail_on_even).on_error(crate::ErrorPolicy::Skip));
+    let mut results = collect(results);
+    results.sort();
+    assert_eq!(results, (0..100u64).filter(|x| x % 2 == 1).collect::<Vec<_>>());
+}
+
+#[test]
+fn send_errors_to_dead_letters() {
+    let (sink, letters) = crate::dead_letters(100);
+    let results = spawn_job(28, 4, source(0..100), move || {
+        let policy = crate::ErrorPolicy::DeadLetter(sink.clone());
+        move |st| st.map(fail_on_even).on_error(policy)
+    });
+    let (results, letters) = with_timeout(move || {
+        let results = block_on(results.map(|r| r.expect("job failed;")).collect::<Vec<_>>());
+        (results, block_on(letters.collect::<Vec<_>>()))
+    });
+    assert_eq!(results.len(), 50);
+    let mut errors = letters
+        .iter()
+        .map(|e| e.error().to_string())
+        .collect::<Vec<_>>();
+    errors.sort();
+    let mut expected = (0..100u64)
+        .filter(|x| x % 2 == 0)
+        .map(|x| format!("even {}", x))
+        .collect::<Vec<_>>();
+    expected.sort();
+    assert_eq!(errors, expected);
+    assert!(letters
+        .iter()
+        .all(|e| e.operator() == Some("on_error") && e.worker_index().is_some()));
+}
+
+fn retried_job(
+    job_id: u64, fails: u32, policy: crate::RetryPolicy,
+) -> (Vec<Result<u64, crate::JobError>>, Vec<std::sync::Arc<std::sync::atomic::AtomicU32>>) {
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+
+    let attempts = (0..10).map(|_| Arc::new(AtomicU32::new(0))).collect::<Vec<_>>();
+    let source = futures::stream::iter(attempts.clone().into_iter().enumerate()).map(|(x, a)| Ok((x as u64, a)));
+    let results = spawn_job(job_id, 2, source, move || {
+        let policy = policy.clone();
+        move |st| {
+            st.map(Ok).then_retry(
+                move |(x, attempts)| async move {
+                    if attempts.fetch_add(1, Ordering::SeqCst) < fails {
+                        Err(FError::StrHint(format!("fail on {}", x)))
+                    } else {
+                        Ok(x)
+                    }
+                },
+                policy,
+            )
+        }
+    });
+    (with_timeout(move || block_on(results.collect::<Vec<_>>())), attempts)
+}
+
+#[test]
+fn retry_until_success() {
+    let policy = crate::RetryPolicy::new(3).initial_backoff(std::time::Duration::from_millis(1));
+    let (results, attempts) = retried_job(29, 2, policy);
+    let mut results = results
+        .into_iter()
+        .map(|r| r.expect("job failed;"))
+        .collect::<Vec<_>>();
+    results.sort();
+    assert_eq!(results, (0..10u64).collect::<Vec<_>>());
+    assert!(attempts
+        .iter()
+        .all(|a| a.load(std::sync::atomic::Ordering::SeqCst) == 3));
+}
+
+#[test]
+fn retry_gives_up_with_last_error() {
+    let policy = crate::RetryPolicy::new(2).initial_backoff(std::time::Duration::from_millis(1));
+    let (results, attempts) = retried_job(30, 5, policy);
+    let error = results.last().unwrap().as_ref().unwrap_err();
+    assert!(error.error().to_string().starts_with("fail on"));
+    assert!(attempts
+        .iter()
+        .all(|a| a.load(std::sync::atomic::Ordering::SeqCst) <= 2));
+    assert!(attempts
+        .iter()
+        .any(|a| a.load(std::sync::atomic::Ordering::SeqCst) == 2));
+}
+
+#[test]
+fn errors_not_retryable_fail_at_once() {
+    let policy = crate::RetryPolicy::new(3).retry_if(|_| false);
+    let (results, attempts) = retried_job(31, 1, policy);
+    assert!(results.last().unwrap().is_err());
+    assert!(attempts
+        .iter()
+        .all(|a| a.load(std::sync::atomic::Ordering::SeqCst) <= 1));
+}
+
+/// A future of `x` which counts futures running at the same time, later items are ready earlier;
+fn delayed(
+    x: Result<u64, FError>, running: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+    max_running: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+) -> impl std::future::Future<Output = Result<u64, FError>> {
+    use std::sync::atomic::Ordering;
+
+    async move {
+        let x = x?;
+        max_running.fetch_max(running.fetch_add(1, Ordering::SeqCst) + 1, Ordering::SeqCst);
+        sandflow_executor::delay(std::time::Duration::from_millis((20 - x) * 5)).await;
+        running.fetch_sub(1, Ordering::SeqCst);
+        Ok(x)
+    }
+}
+
+#[test]
+fn then_buffered_keeps_order() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let max_running = Arc::new(AtomicUsize::new(0));
+    let m = max_running.clone();
+    let results = spawn_job(32, 1, source(0..20), move || {
+        let (running, max_running) = (Arc::new(AtomicUsize::new(0)), m.clone());
+        move |st| {
+            st.map(Ok)
+                .then_buffered(4, move |x| delayed(x, running.clone(), max_running.clone()))
+        }
+    });
+    let results = with_timeout(move || collect(results));
+    assert_eq!(results, (0..20u64).collect::<Vec<_>>());
+    assert_eq!(max_running.load(Ordering::SeqCst), 4);
+}
+
+#[test]
+fn then_unordered_emits_by_completion() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let max_running = Arc::new(AtomicUsize::new(0));
+    let m = max_running.clone();
+    let results = spawn_job(33, 1, source(0..20), move || {
+        let (running, max_running) = (Arc::new(AtomicUsize::new(0)), m.clone());
+        move |st| {
+            st.map(Ok)
+                .then_unordered(4, move |x| delayed(x, running.clone(), max_running.clone()))
+        }
+    });
+    let mut results = with_timeout(move || collect(results));
+    // the first 4 items run together, and the last of them is ready first;
+    assert_eq!(results[0], 3);
+    results.sort();
+    assert_eq!(results, (0..20u64).collect::<Vec<_>>());
+    assert_eq!(max_running.load(Ordering::SeqCst), 4);
+}
+
+#[test]
+fn filter_ok_items() {
+    let results = spawn_job(34, 4, source(0..100), || |st| st.map(Ok).filter(|x| x % 3 == 0));
+    let mut results = collect(results);
+    results.sort();
+    assert_eq!(results, (0..100u64).filter(|x| x % 3 == 0).collect::<Vec<_>>());
+
+    let results = spawn_job(35, 4, source(0..100), || {
+        |st| {
+            st.map(Ok)
+                .filter_map(|x| if x % 3 == 0 { Some(x * 2) } else { None })
+        }
+    });
+    let mut results = collect(results);
+    results.sort();
+    assert_eq!(
+        results,
+        (0..100u64)
+            .filter(|x| x % 3 == 0)
+            .map(|x| x * 2)
+            .collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn error_of_try_filter_fails_job() {
+    let results = spawn_job(36, 4, source(0..100), || {
+        |st| {
+            st.map(Ok)
+                .try_filter(|x| if *x == 50 { Err(FError::StrHint("filter on 50".to_owned())) } else { Ok(x % 2 == 0) })
+        }
+    });
+    let results = with_timeout(move || block_on(results.collect::<Vec<_>>()));
+    assert!(results
+        .iter()
+        .rev()
+        .skip(1)
+        .all(|r| r.as_ref().is_ok_and(|x| x % 2 == 0)));
+    let error = results.last().unwrap().as_ref().unwrap_err();
+    assert_eq!(error.error().to_string(), "filter on 50");
+}
+
+#[test]
+fn combinators_keep_order_of_worker() {
+    let results = spawn_job(37, 1, source(0..100), || |st| st.map(Ok).take_while(|x| *x < 10));
+    assert_eq!(collect(results), (0..10u64).collect::<Vec<_>>());
+
+    let results = spawn_job(38, 1, source(0..100), || |st| st.map(Ok).skip_while(|x| *x < 90));
+    assert_eq!(collect(results), (90..100u64).collect::<Vec<_>>());
+
+    let results = spawn_job(39, 1, source(0..100), || {
+        |st| {
+            st.map(Ok).scan(0, |sum, x| {
+                *sum += x;
+                if *sum < 50 {
+                    Some(*sum)
+                } else {
+                    None
+                }
+            })
+        }
+    });
+    assert_eq!(collect(results), vec![0, 1, 3, 6, 10, 15, 21, 28, 36, 45]);
+
+    let results = spawn_job(40, 1, source(0..10), || |st| st.map(Ok).chunks(4));
+    assert_eq!(collect(results), vec![vec![0, 1, 2, 3], vec![4, 5, 6, 7], vec![8, 9]]);
+
+    let results = spawn_job(41, 1, source(10..15), || |st| st.map(Ok).enumerate());
+    assert_eq!(
+        collect(results),
+        (10..15u64)
+            .enumerate()
+            .map(|(i, x)| (i as u64, x))
+            .collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn enumerate_items_of_each_worker() {
+    let results = spawn_job(42, 4, source(0..100), || |st| st.map(Ok).enumerate());
+    let mut indexes = collect(results).into_iter().map(|(i, _)| i).collect::<Vec<_>>();
+    indexes.sort();
+    // indexes of each worker start from 0;
+    assert_eq!(indexes.iter().filter(|i| **i == 0).count(), 4);
+    assert_eq!(indexes.len(), 100);
+}
+
+#[test]
+fn limit_stops_infinite_source() {
+    let results = spawn_job(43, 4, futures::stream::iter(0u64..).map(Ok), || {
+        |st| st.map(|x| Ok(x * 2)).exchange(|x| *x).map(Ok).limit(100)
+    });
+    let results = with_timeout(move || collect(results));
+    assert_eq!(results.len(), 100);
+    assert!(results.iter().all(|x| x % 2 == 0));
+
+    let results = spawn_job(44, 4, source(0..10), || |st| st.map(Ok).limit(100));
+    assert_eq!(collect(results).len(), 10);
+
+    let results = spawn_job(45, 1, futures::stream::iter(0u64..).map(Ok), || |st| st.map(Ok).limit(0));
+    assert!(with_timeout(move || collect(results)).is_empty());
+}
+
+#[test]
+fn sort_by_key_of_all_workers() {
+    for (job_id, parallel) in [(46, 1), (47, 4)] {
+        // keys are shuffled and repeated;
+        let results = spawn_job(job_id, parallel, source(0..1000), || |st| st.map(|x| Ok((x * 37) % 500)).sort_by_key(|x| *x));
+        let results = with_timeout(move || collect(results));
+        let mut expected = (0..1000u64).map(|x| (x * 37) % 500).collect::<Vec<_>>();
+        expected.sort();
+        assert_eq!(results, expected);
+    }
+}
+
+#[test]
+fn sort_by_key_of_empty_input() {
+    let results = spawn_job(48, 4, source(0..0), || |st| st.map(Ok).sort_by_key(|x| *x));
+    assert!(with_timeout(move || collect(results)).is_empty());
+}
+
+#[test]
+fn top_k_of_all_workers() {
+    for (job_id, parallel) in [(49, 1), (50, 4)] {
+        let results = spawn_job(job_id, parallel, source(0..1000), || |st| st.map(|x| Ok((x * 37) % 1000)).top_k(5, |x| *x));
+        assert_eq!(with_timeout(move || collect(results)), vec![999, 998, 997, 996, 995]);
+    }
+    // fewer items than `k` are all emitted;
+    let results = spawn_job(51, 4, source(0..3), || |st| st.map(Ok).top_k(5, |x| *x));
+    assert_eq!(with_timeout(move || collect(results)), vec![2, 1, 0]);
+}
+
+#[test]
+fn distinct_by_key() {
+    for (job_id, parallel) in [(52, 1), (53, 4)] {
+        let results = spawn_job(job_id, parallel, source(0..1000), || |st| st.map(Ok).distinct_by(|x| x % 10));
+        let mut keys = with_timeout(move || collect(results))
+            .into_iter()
+            .map(|x| x % 10)
+            .collect::<Vec<_>>();
+        keys.sort();
+        assert_eq!(keys, (0..10u64).collect::<Vec<_>>());
+    }
+}
+
+#[test]
+fn bounded_distinct_forgets_oldest_keys() {
+    let source = futures::stream::iter(vec![0u64, 1, 1, 2, 0, 2]).map(Ok);
+    let results = spawn_job(54, 1, source, || |st| st.map(Ok).distinct_by_bounded(|x| *x, 2, None));
+    // 0 is forgotten once 2 is seen;
+    assert_eq!(with_timeout(move || collect(results)), vec![0, 1, 2, 0]);
+}
+
+#[test]
+fn window_by_key_of_all_workers() {
+    for (job_id, parallel) in [(55, 1), (56, 4)] {
+        let results = spawn_job(job_id, parallel, source(0..100), || {
+            |st| {
+                st.map(Ok)
+                    .window_by_key(|x| x % 2, crate::Window::Tumbling(std::time::Duration::from_secs(60)))
+            }
+        });
+        let results = with_timeout(move || collect(results));
+        for key in 0..2u64 {
+            let mut items = results
+                .iter()
+                .filter(|r| r.key == key)
+                .flat_map(|r| r.items.iter().copied())
+                .collect::<Vec<_>>();
+            items.sort();
+            assert_eq!(items, (0..100u64).filter(|x| x % 2 == key).collect::<Vec<_>>());
+        }
+        assert!(results.iter().all(|r| r.window.end - r.window.start == 60_000));
+    }
+}
+
+/// Items `0..10` arrive at once, and items `10..20` arrive after a pause;
+fn paused_source() -> impl futures::Stream<Item = Result<u64, FError>> + Send + Unpin + 'static {
+    let delayed = futures::stream::once(sandflow_executor::delay(std::time::Duration::from_millis(300)))
+        .flat_map(|_| futures::stream::iter(10..20));
+    futures::stream::iter(0..10).chain(delayed).map(Ok).boxed()
+}
+
+#[test]
+fn session_windows_close_after_gap() {
+    let gap = crate::Window::Session(std::time::Duration::from_millis(50));
+    let results = spawn_job(57, 1, paused_source(), move || move |st| st.map(Ok).window_by_key(|_| 0u64, gap));
+    let results = with_timeout(move || collect(results));
+    let items = results.iter().map(|r| r.items.clone()).collect::<Vec<_>>();
+    assert_eq!(items, vec![(0..10u64).collect::<Vec<_>>(), (10..20u64).collect::<Vec<_>>()]);
+    assert!(results[0].window.end <= results[1].window.start);
+
+    let results = spawn_job(58, 1, paused_source(), move || move |st| st.map(Ok).window(gap));
+    let items = with_timeout(move || collect(results))
+        .into_iter()
+        .map(|r| r.items)
+        .collect::<Vec<_>>();
+    assert_eq!(items, vec![(0..10u64).collect::<Vec<_>>(), (10..20u64).collect::<Vec<_>>()]);
+}
+
+#[test]
+fn event_window_after_exchange_timed() {
+    for (job_id, parallel) in [(59, 1), (60, 4)] {
+        let results = spawn_job(job_id, parallel, source(0..100), || {
+            |st| {
+                let (windows, _late) = st
+                    .map(Ok)
+                    .assign_timestamps(|x| *x * 10, std::time::Duration::ZERO)
+                    .map(|x| x * 2)
+                    .exchange_timed(|x| *x / 2 % 3)
+                    .event_window(crate::Window::Tumbling(std::time::Duration::from_millis(200)));
+                windows
+            }
+        });
+        let results = with_timeout(move || collect(results));
+        let mut items = results
+            .iter()
+            .flat_map(|r| r.items.iter().copied())
+            .collect::<Vec<_>>();
+        items.sort();
+        // items behind the watermark are late, so none is late if watermarks of all workers are merged;
+        assert_eq!(items, (0..100u64).map(|x| x * 2).collect::<Vec<_>>());
+        let mut windows = results.iter().map(|r| r.window.start).collect::<Vec<_>>();
+        windows.sort();
+        windows.dedup();
+        assert_eq!(windows, (0..1000u64).step_by(200).collect::<Vec<_>>());
+        assert_eq!(results.len(), 5 * parallel.min(3));
+    }
+}
+
+#[test]
+fn process_with_states_of_keys() {
+    for (job_id, parallel) in [(61, 1), (62, 4)] {
+        let results = spawn_job(job_id, parallel, source(0..100), || {
+            |st| {
+                st.map(Ok).key_by(|x| x % 5).process(|ctx, x| {
+                    let key = *ctx.key();
+                    assert_eq!(x % 5, key);
+                    let mut count = ctx.value::<u64>("count");
+                    count.update(|count| Some(count.unwrap_or(0) + 1));
+                    let count = *count.get().unwrap();
+                    ctx.list::<u64>("items").push(x);
+                    if count == 20 {
+                        let mut items = ctx.list::<u64>("items").take();
+                        items.sort();
+                        ctx.emit((key, items));
+                    }
+                    Ok(())
+                })
+            }
+        });
+        let mut results = with_timeout(move || collect(results));
+        results.sort();
+        let expected = (0..5u64)
+            .map(|key| (key, (0..100u64).filter(|x| x % 5 == key).collect::<Vec<_>>()))
+            .collect::<Vec<_>>();
+        assert_eq!(results, expected);
+    }
+}
+
+#[test]
+fn jobs_with_same_id_spill_to_same_dir() {
+    let dir = std::env::temp_dir().join(format!("sandflow-test-{}", std::process::id()));
+    let jobs = (0..2)
+        .map(|_| {
+            let config = crate::JobConfig::new(63, 2)
+                .memory_budget(256)
+                .spill_dir(dir.clone());
+            crate::spawn_job_with(config, source(0..5000), || {
+                |st| {
+                    st.map(Ok).fold_by_key_spillable(
+                        |x| x % 100,
+                        Vec::new,
+                        |mut items: Vec<u64>, x| {
+                            items.push(x);
+                            items
+                        },
+                        |mut items, other| {
+                            items.extend(other);
+                            items
+                        },
+                    )
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    let expected = (0..100u64)
+        .map(|key| (key, (0..5000u64).filter(|x| x % 100 == key).collect::<Vec<_>>()))
+        .collect::<Vec<_>>();
+    for results in jobs {
+        let mut results = with_timeout(move || collect(results));
+        for (_, items) in results.iter_mut() {
+            items.sort();
+        }
+        results.sort();
+        assert_eq!(results, expected);
+    }
+    // runs are removed once they are read back;
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+    std::fs::remove_dir(&dir).unwrap();
+}
+
+/// Read one result of a job on an infinite source with `config`, and count items taken from the source after a while;
+fn items_taken_ahead(config: crate::JobConfig, exchange_capacity: Option<usize>) -> usize {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let taken = Arc::new(AtomicUsize::new(0));
+    let counter = taken.clone();
+    let source = futures::stream::iter(0u64..).map(move |x| {
+        counter.fetch_add(1, Ordering::SeqCst);
+        Ok(x)
+    });
+    let mut results = crate::spawn_job_with(config, source, || {
+        move |st| match exchange_capacity {
+            Some(capacity) => st.map(Ok).exchange_with_capacity(|x| *x, capacity).map(Ok),
+            None => st.map(Ok).exchange(|x| *x).map(Ok),
+        }
+    });
+    assert!(block_on(results.next()).is_some());
+    std::thread::sleep(std::time::Duration::from_millis(100));
+    taken.load(Ordering::SeqCst)
+}
+
+#[test]
+fn capacities_bound_items_in_flight() {
+    let small = crate::JobConfig::new(64, 2)
+        .channel_capacity(1)
+        .result_capacity(1);
+    assert!(items_taken_ahead(small, None) < 50);
+
+    // only the exchange has a large capacity, so more items are taken;
+    let exchanged = crate::JobConfig::new(65, 2)
+        .channel_capacity(1)
+        .result_capacity(1);
+    let taken = items_taken_ahead(exchanged, Some(100));
+    assert!((100..1000).contains(&taken), "{} items are taken", taken);
+
+    let results = crate::JobConfig::new(66, 2)
+        .channel_capacity(1)
+        .result_capacity(100);
+    let taken = items_taken_ahead(results, None);
+    assert!((100..1000).contains(&taken), "{} items are taken", taken);
+}